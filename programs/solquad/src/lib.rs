@@ -1,4 +1,5 @@
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
 
 declare_id!("5sFUqUTjAMJARrEafMX8f4J1LagdUQ9Y8TR8HwGNHkU8");
 
@@ -6,20 +7,35 @@ declare_id!("5sFUqUTjAMJARrEafMX8f4J1LagdUQ9Y8TR8HwGNHkU8");
 pub mod solquad {
     use super::*;
 
-    pub fn initialize_escrow(ctx: Context<InitializeEscrow>, amount: u64) -> Result<()> {
+    pub fn initialize_escrow(ctx: Context<InitializeEscrow>, round_id: u64, amount: u64) -> Result<()> {
         let escrow_account = &mut ctx.accounts.escrow_account;
         escrow_account.escrow_creator = ctx.accounts.escrow_signer.key();
+        escrow_account.round_id = round_id;
         escrow_account.creator_deposit_amount = amount;
         escrow_account.total_projects = 0;
+        escrow_account.refunded_amount = 0;
+        escrow_account.distributed = false;
 
         Ok(())
     }
 
-    pub fn initialize_pool(ctx: Context<InitializePool>) -> Result<()> {
+    pub fn initialize_pool(ctx: Context<InitializePool>, round_id: u64, end_slot: u64) -> Result<()> {
         let pool_account = &mut ctx.accounts.pool_account;
         pool_account.pool_creator = ctx.accounts.pool_signer.key();
+        pool_account.round_id = round_id;
+        pool_account.end_slot = end_slot;
         pool_account.total_projects = 0;
         pool_account.total_votes = 0;
+        pool_account.contributor_allowlist = None;
+        pool_account.remainder_recipient = RemainderRecipient::Creator;
+        pool_account.carryover_amount = 0;
+        pool_account.min_contribution = 0;
+        pool_account.early_boost_bps = 0;
+        pool_account.early_boost_deadline_slot = 0;
+        pool_account.max_match_per_project = None;
+        pool_account.contribution_decimals = NORMALIZED_DECIMALS;
+        pool_account.carryover_to = None;
+        pool_account.distributed = false;
 
         Ok(())
     }
@@ -32,6 +48,8 @@ pub mod solquad {
         project_account.votes_count = 0;
         project_account.voter_amount = 0;
         project_account.distributed_amt = 0;
+        project_account.contributions = Vec::new();
+        project_account.match_score = 0;
 
         Ok(())
     }
@@ -41,6 +59,26 @@ pub mod solquad {
         let pool_account = &mut ctx.accounts.pool_account;
         let project_account = &ctx.accounts.project_account;
 
+        validate_pool_address(pool_account, ctx.program_id)?;
+        require!(
+            pool_account.pool_creator == escrow_account.escrow_creator
+                && pool_account.round_id == escrow_account.round_id,
+            ErrorCode::RoundMismatch
+        );
+        require_keys_eq!(
+            project_account.key(),
+            project_pda(&pool_account.key(), &project_account.project_owner, ctx.program_id).0,
+            ErrorCode::ProjectNotInPool
+        );
+        // Re-adding a project is a no-op, it must not be listed or paid twice
+        if pool_account.projects.contains(&project_account.project_owner) {
+            return Ok(());
+        }
+        require!(
+            pool_account.distinct_projects().len() < MAX_AUDITED_PROJECTS,
+            ErrorCode::TooManyProjects
+        );
+
         pool_account.projects.push(
             project_account.project_owner
         );
@@ -56,42 +94,494 @@ pub mod solquad {
     pub fn vote_for_project(ctx: Context<VoteForProject>, amount: u64) -> Result<()> {
         let pool_account = &mut ctx.accounts.pool_account;
         let project_account = &mut ctx.accounts.project_account;
+        let voter = ctx.accounts.voter_sig.key();
+        let slot = Clock::get()?.slot;
+
+        check_contributor(pool_account, &voter, slot)?;
+        validate_pool_address(pool_account, ctx.program_id)?;
+        validate_pool_project(ctx.program_id, pool_account, project_account)?;
+
+        record_contribution(pool_account, project_account, voter, amount, slot)?;
+        fit_account(
+            project_account,
+            &ctx.accounts.voter_sig,
+            &ctx.accounts.system_program,
+        )
+    }
+
+    // Contribute to several projects of the pool at once, each project in the
+    // batch passed once through remaining_accounts. Either every contribution
+    // is recorded or, on the first failing one, none is.
+    pub fn contribute_batch<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, ContributeBatch<'info>>,
+        contributions: Vec<BatchContribution>,
+    ) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+        let voter = ctx.accounts.voter_sig.key();
+        let slot = Clock::get()?.slot;
+
+        require!(
+            contributions.len() <= MAX_BATCH_CONTRIBUTIONS,
+            ErrorCode::TooManyContributions
+        );
+        check_contributor(pool_account, &voter, slot)?;
+        validate_pool_address(pool_account, ctx.program_id)?;
+
+        let mut projects: Vec<Account<'info, Project>> =
+            Vec::with_capacity(ctx.remaining_accounts.len());
+        for account_info in ctx.remaining_accounts {
+            let project = Account::<Project>::try_from(account_info)?;
+            validate_pool_project(ctx.program_id, pool_account, &project)?;
+            require!(
+                !projects.iter().any(|p| p.key() == project.key()),
+                ErrorCode::ProjectAccountsMismatch
+            );
+            projects.push(project);
+        }
+
+        // Contributions move no tokens, there is no balance to check the total
+        // against, only that it is representable
+        contributions
+            .iter()
+            .try_fold(0u64, |total, contribution| total.checked_add(contribution.amount))
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        for contribution in contributions.iter() {
+            let project_account = projects
+                .iter_mut()
+                .find(|project| project.key() == contribution.project)
+                .ok_or(ErrorCode::ProjectNotInPool)?;
+            record_contribution(
+                pool_account,
+                project_account,
+                voter,
+                contribution.amount,
+                slot,
+            )?;
+        }
+
+        for project_account in projects.iter() {
+            fit_account(
+                project_account,
+                &ctx.accounts.voter_sig,
+                &ctx.accounts.system_program,
+            )?;
+            project_account.exit(ctx.program_id)?;
+        }
+
+        Ok(())
+    }
+
+    // Adding the first contributor turns the pool into a private round, the
+    // creator pays for the space a growing allowlist takes
+    pub fn allow_contributor(ctx: Context<AllowContributor>, contributor: Pubkey) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+        let allowlist = pool_account.contributor_allowlist.get_or_insert_with(Vec::new);
+
+        if !allowlist.contains(&contributor) {
+            allowlist.push(contributor);
+        }
+
+        fit_account(
+            pool_account,
+            &ctx.accounts.pool_creator,
+            &ctx.accounts.system_program,
+        )
+    }
+
+    // The pool stays private even once the allowlist is emptied
+    pub fn disallow_contributor(ctx: Context<ManagePool>, contributor: Pubkey) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+
+        if let Some(allowlist) = pool_account.contributor_allowlist.as_mut() {
+            allowlist.retain(|allowed| *allowed != contributor);
+        }
+
+        Ok(())
+    }
+
+    pub fn get_project_stats(ctx: Context<GetProjectStats>) -> Result<ProjectStats> {
+        let project_account = &ctx.accounts.project_account;
+
+        Ok(ProjectStats {
+            raw_total: project_account.voter_amount,
+            distinct_donors: u32::try_from(project_account.contributions.len())
+                .map_err(|_| ErrorCode::MathOverflow)?,
+            weight: project_account.match_score,
+        })
+    }
+
+    // A round can only be extended while it is still open, and never shortened
+    pub fn extend_round(ctx: Context<ManagePool>, new_end_slot: u64) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+
+        require!(
+            Clock::get()?.slot <= pool_account.end_slot,
+            ErrorCode::RoundEnded
+        );
+        require!(
+            new_end_slot > pool_account.end_slot,
+            ErrorCode::EndSlotNotLater
+        );
+
+        pool_account.end_slot = new_end_slot;
+
+        Ok(())
+    }
+
+    // Every project in the pool is passed through remaining_accounts
+    pub fn get_contributor_total<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, GetContributorTotal<'info>>,
+        contributor: Pubkey,
+    ) -> Result<u64> {
+        let pool_account = &ctx.accounts.pool_account;
+        let projects = load_pool_projects(
+            ctx.program_id,
+            &pool_account.key(),
+            pool_account,
+            ctx.remaining_accounts,
+        )?;
+
+        let total = projects
+            .iter()
+            .flat_map(|project| project.contributions.iter())
+            .filter(|contribution| contribution.contributor == contributor)
+            .try_fold(0u64, |total, contribution| total.checked_add(contribution.amount))
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(total)
+    }
+
+    // Matching still available to the round, nothing once it was distributed
+    pub fn get_matching_balance(ctx: Context<GetMatchingBalance>) -> Result<u64> {
+        let escrow_account = &ctx.accounts.escrow_account;
+        let pool_account = &ctx.accounts.pool_account;
 
-        for i in 0..pool_account.projects.len() {
-            if pool_account.projects[i] == project_account.project_owner {
-                project_account.votes_count += 1;
-                project_account.voter_amount += amount;
+        require!(
+            pool_account.pool_creator == escrow_account.escrow_creator
+                && pool_account.round_id == escrow_account.round_id,
+            ErrorCode::RoundMismatch
+        );
+
+        if escrow_account.distributed {
+            return Ok(0);
+        }
+        Ok(escrow_account.creator_deposit_amount)
+    }
+
+    // Recompute the counters cached on the pool and its projects from the
+    // contributions. Every project in the pool is passed through remaining_accounts.
+    pub fn repair_pool<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, ManagePool<'info>>,
+    ) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+        let mut projects = load_pool_projects(
+            ctx.program_id,
+            &pool_account.key(),
+            pool_account,
+            ctx.remaining_accounts,
+        )?;
+
+        let mut total_votes: u64 = 0;
+        for project_account in projects.iter_mut() {
+            if repair_project(project_account)? {
+                msg!("Repaired derived fields of project {}", project_account.key());
             }
+            total_votes = total_votes
+                .checked_add(project_account.votes_count)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+
+        if pool_account.total_votes != total_votes {
+            msg!(
+                "Repaired pool total_votes from {} to {}",
+                pool_account.total_votes,
+                total_votes
+            );
+            pool_account.total_votes = total_votes;
+        }
+
+        for project_account in projects.iter() {
+            project_account.exit(ctx.program_id)?;
         }
 
-        pool_account.total_votes += 1;
+        Ok(())
+    }
+
+    // Contributions below the minimum are rejected as dust
+    pub fn set_min_contribution(ctx: Context<ManagePool>, min_contribution: u64) -> Result<()> {
+        ctx.accounts.pool_account.min_contribution = min_contribution;
 
         Ok(())
     }
 
-    pub fn distribute_escrow_amount(ctx: Context<DistributeEscrowAmount>) -> Result<()> {
+    // Contributions up to and including the deadline slot weigh bps extra,
+    // a bps of 0 turns the boost off. Fixed once the first contribution is in
+    pub fn set_early_boost(
+        ctx: Context<ManagePool>,
+        early_boost_bps: u16,
+        early_boost_deadline_slot: u64,
+    ) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+        require!(pool_account.total_votes == 0, ErrorCode::RoundAlreadyStarted);
+        pool_account.early_boost_bps = early_boost_bps;
+        pool_account.early_boost_deadline_slot = early_boost_deadline_slot;
+
+        Ok(())
+    }
+
+    // None lifts the cap, the overflow of a capped project goes to the others
+    pub fn set_max_match_per_project(
+        ctx: Context<ManagePool>,
+        max_match_per_project: Option<u64>,
+    ) -> Result<()> {
+        ctx.accounts.pool_account.max_match_per_project = max_match_per_project;
+
+        Ok(())
+    }
+
+    // Decimals of the contributed token, fixed once the first contribution is in
+    // so every weight in the round is on the same scale
+    pub fn set_contribution_decimals(ctx: Context<ManagePool>, decimals: u8) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+        require!(
+            decimals <= MAX_CONTRIBUTION_DECIMALS,
+            ErrorCode::InvalidContributionDecimals
+        );
+        require!(pool_account.total_votes == 0, ErrorCode::RoundAlreadyStarted);
+        pool_account.contribution_decimals = decimals;
+
+        Ok(())
+    }
+
+    // Round the carried over funds go to, None keeps them on the pool
+    pub fn set_carryover_to(ctx: Context<ManagePool>, carryover_to: Option<Pubkey>) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+        require!(
+            carryover_to != Some(pool_account.key()),
+            ErrorCode::CarryoverTargetMismatch
+        );
+        pool_account.carryover_to = carryover_to;
+
+        Ok(())
+    }
+
+    // Move the funds set aside on a pool into the matching of its linked round
+    pub fn carry_over(ctx: Context<CarryOver>) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+        let successor_pool = &ctx.accounts.successor_pool;
+        let successor_escrow = &mut ctx.accounts.successor_escrow;
+
+        require!(
+            pool_account.carryover_to == Some(successor_pool.key()),
+            ErrorCode::CarryoverTargetMismatch
+        );
+        validate_pool_address(successor_pool, ctx.program_id)?;
+        require!(
+            successor_pool.pool_creator == successor_escrow.escrow_creator
+                && successor_pool.round_id == successor_escrow.round_id,
+            ErrorCode::RoundMismatch
+        );
+        require!(!successor_escrow.distributed, ErrorCode::AlreadyDistributed);
+
+        successor_escrow.creator_deposit_amount = successor_escrow
+            .creator_deposit_amount
+            .checked_add(pool_account.carryover_amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        pool_account.carryover_amount = 0;
+
+        Ok(())
+    }
+
+    pub fn set_remainder_recipient(
+        ctx: Context<ManagePool>,
+        remainder_recipient: RemainderRecipient,
+    ) -> Result<()> {
+        ctx.accounts.pool_account.remainder_recipient = remainder_recipient;
+
+        Ok(())
+    }
+
+    // Runs once the round's end slot has passed, every project in the pool is
+    // passed through remaining_accounts. If none of them is eligible the deposit
+    // is refunded to the creator when refund_unallocated is set, carried over
+    // when the pool links a next round and left in the escrow otherwise.
+    pub fn distribute_escrow_amount<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, DistributeEscrowAmount<'info>>,
+        refund_unallocated: bool,
+    ) -> Result<()> {
         let escrow_account = &mut ctx.accounts.escrow_account;
         let pool_account = &mut ctx.accounts.pool_account;
-        let project_account = &mut ctx.accounts.project_account;
-  
-        for i in 0..escrow_account.project_reciever_addresses.len() {
-            let distributable_amt: u64;
-            let votes: u64;
-
-            let project = pool_account.projects[i];
-            if project == project_account.project_owner {
-                votes = project_account.votes_count;
-            } else {
-                votes = 0;
+
+        require!(!escrow_account.distributed, ErrorCode::AlreadyDistributed);
+        // Scores are only final once no more contributions can come in
+        require!(
+            Clock::get()?.slot > pool_account.end_slot,
+            ErrorCode::RoundNotEnded
+        );
+        validate_pool_address(pool_account, ctx.program_id)?;
+
+        // Each escrow only funds the pool of its own creator and round
+        require!(
+            pool_account.pool_creator == escrow_account.escrow_creator
+                && pool_account.round_id == escrow_account.round_id,
+            ErrorCode::RoundMismatch
+        );
+
+        let mut projects = load_pool_projects(
+            ctx.program_id,
+            &pool_account.key(),
+            pool_account,
+            ctx.remaining_accounts,
+        )?;
+
+        require!(
+            projects.len() <= MAX_AUDITED_PROJECTS,
+            ErrorCode::TooManyProjects
+        );
+
+        // A project is eligible once it has at least one contributor
+        let eligible_projects = projects
+            .iter()
+            .filter(|project| project.votes_count > 0)
+            .count();
+
+        if eligible_projects == 0 {
+            let amount = escrow_account.creator_deposit_amount;
+            if refund_unallocated {
+                escrow_account.refunded_amount = escrow_account
+                    .refunded_amount
+                    .checked_add(amount)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                escrow_account.creator_deposit_amount = 0;
+                escrow_account.distributed = true;
+                pool_account.distributed = true;
+            } else if pool_account.carryover_to.is_some() {
+                // A linked round takes the deposit over, see carry_over
+                pool_account.carryover_amount = pool_account
+                    .carryover_amount
+                    .checked_add(amount)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                escrow_account.creator_deposit_amount = 0;
+                escrow_account.distributed = true;
+                pool_account.distributed = true;
             }
 
-            if votes != 0 {
-                distributable_amt = (votes / pool_account.total_votes) * escrow_account.creator_deposit_amount as u64;
-            } else {
-                distributable_amt = 0;
+            emit!(NoEligibleProjects {
+                escrow: escrow_account.key(),
+                pool: pool_account.key(),
+                amount,
+                refunded: refund_unallocated,
+            });
+
+            // Only a settled escrow is audited, a later attempt can still
+            // refund the deposit left in escrow or carry it over
+            if escrow_account.distributed {
+                ctx.accounts
+                    .audit_account
+                    .record(pool_account.key(), 0, &projects);
             }
 
-            project_account.distributed_amt = distributable_amt;
+            return Ok(());
+        }
+
+        let total_weight = projects
+            .iter()
+            .try_fold(0u128, |total, project| total.checked_add(project.match_score))
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        // Zero-amount contributions make a project eligible without giving it
+        // any weight, there is nothing to split the deposit by
+        require!(total_weight > 0, ErrorCode::NothingToDistribute);
+
+        let cap = pool_account.max_match_per_project.unwrap_or(u64::MAX);
+        let mut distributed: u64 = 0;
+        if eligible_projects == 1 {
+            // A lone eligible project takes the whole deposit, whatever its share
+            let amount = escrow_account.creator_deposit_amount.min(cap);
+            for project_account in projects.iter_mut() {
+                project_account.distributed_amt = if project_account.votes_count > 0 {
+                    amount
+                } else {
+                    0
+                };
+            }
+            distributed = amount;
+        } else {
+            let scores: Vec<u128> = projects.iter().map(|project| project.match_score).collect();
+            let allocations =
+                allocate_matching(escrow_account.creator_deposit_amount, &scores, cap)?;
+            for (project_account, allocation) in projects.iter_mut().zip(allocations) {
+                project_account.distributed_amt = allocation;
+                distributed = distributed
+                    .checked_add(allocation)
+                    .ok_or(ErrorCode::MathOverflow)?;
+            }
+        }
+
+        // Integer division and the cap leave a remainder, hand it to the configured recipient
+        let remainder = escrow_account
+            .creator_deposit_amount
+            .checked_sub(distributed)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        match pool_account.remainder_recipient {
+            RemainderRecipient::Creator => {
+                escrow_account.refunded_amount = escrow_account
+                    .refunded_amount
+                    .checked_add(remainder)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                escrow_account.creator_deposit_amount = distributed;
+            }
+            RemainderRecipient::TopProject => {
+                // Ties go to the lowest project address, projects without weight
+                // or that the remainder would push past the cap are passed over
+                let top_project = projects
+                    .iter_mut()
+                    .filter(|project| {
+                        project.match_score > 0
+                            && project.distributed_amt.saturating_add(remainder) <= cap
+                    })
+                    .max_by(|a, b| {
+                        a.match_score
+                            .cmp(&b.match_score)
+                            .then_with(|| b.key().cmp(&a.key()))
+                    });
+                match top_project {
+                    Some(top_project) => {
+                        top_project.distributed_amt = top_project
+                            .distributed_amt
+                            .checked_add(remainder)
+                            .ok_or(ErrorCode::MathOverflow)?;
+                    }
+                    None => {
+                        // No weighted project has room below the cap, the creator gets it back
+                        escrow_account.refunded_amount = escrow_account
+                            .refunded_amount
+                            .checked_add(remainder)
+                            .ok_or(ErrorCode::MathOverflow)?;
+                        escrow_account.creator_deposit_amount = distributed;
+                    }
+                }
+            }
+            RemainderRecipient::Carryover => {
+                pool_account.carryover_amount = pool_account
+                    .carryover_amount
+                    .checked_add(remainder)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                escrow_account.creator_deposit_amount = distributed;
+            }
+        }
+
+        escrow_account.distributed = true;
+        pool_account.distributed = true;
+
+        ctx.accounts
+            .audit_account
+            .record(pool_account.key(), total_weight, &projects);
+
+        for project_account in projects.iter() {
+            project_account.exit(ctx.program_id)?;
         }
 
         Ok(())
@@ -99,12 +589,13 @@ pub mod solquad {
 }
 
 #[derive(Accounts)]
+#[instruction(round_id: u64)]
 pub struct InitializeEscrow<'info> {
     #[account(
         init,
         payer = escrow_signer,
         space = 1024,
-        seeds = [b"escrow".as_ref(), escrow_signer.key().as_ref()],
+        seeds = [b"escrow".as_ref(), escrow_signer.key().as_ref(), round_id.to_le_bytes().as_ref()],
         bump,
     )]
     pub escrow_account: Account<'info, Escrow>,
@@ -114,12 +605,13 @@ pub struct InitializeEscrow<'info> {
 }
 
 #[derive(Accounts)]
+#[instruction(round_id: u64)]
 pub struct InitializePool<'info> {
     #[account(
         init,
         payer = pool_signer,
         space = 1024,
-        seeds = [b"pool".as_ref(), pool_signer.key().as_ref()],
+        seeds = [b"pool".as_ref(), pool_signer.key().as_ref(), round_id.to_le_bytes().as_ref()],
         bump,
     )]
     pub pool_account: Account<'info, Pool>,
@@ -133,7 +625,7 @@ pub struct InitializeProject<'info> {
     #[account(
         init,
         payer = project_owner,
-        space = 32 + 32 + 8 + 8 + 8 + 8,
+        space = 1024,
         seeds = [b"project".as_ref(), pool_account.key().as_ref(), project_owner.key().as_ref()],
         bump,
     )]
@@ -162,6 +654,58 @@ pub struct VoteForProject<'info> {
     pub project_account: Account<'info, Project>,
     #[account(mut)]
     pub voter_sig: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ContributeBatch<'info> {
+    #[account(mut)]
+    pub pool_account: Account<'info, Pool>,
+    #[account(mut)]
+    pub voter_sig: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ManagePool<'info> {
+    pub pool_creator: Signer<'info>,
+    #[account(mut, has_one = pool_creator)]
+    pub pool_account: Account<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct AllowContributor<'info> {
+    #[account(mut)]
+    pub pool_creator: Signer<'info>,
+    #[account(mut, has_one = pool_creator)]
+    pub pool_account: Account<'info, Pool>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CarryOver<'info> {
+    pub pool_creator: Signer<'info>,
+    #[account(mut, has_one = pool_creator)]
+    pub pool_account: Account<'info, Pool>,
+    pub successor_pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub successor_escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct GetProjectStats<'info> {
+    pub project_account: Account<'info, Project>,
+}
+
+#[derive(Accounts)]
+pub struct GetContributorTotal<'info> {
+    pub pool_account: Account<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct GetMatchingBalance<'info> {
+    pub escrow_account: Account<'info, Escrow>,
+    pub pool_account: Account<'info, Pool>,
 }
 
 #[derive(Accounts)]
@@ -172,26 +716,133 @@ pub struct DistributeEscrowAmount<'info> {
     pub escrow_account: Account<'info, Escrow>,
     #[account(mut)]
     pub pool_account: Account<'info, Pool>,
-    #[account(mut)]
-    pub project_account: Account<'info, Project>,
+    // Created by the first attempt, an attempt that leaves the escrow
+    // unsettled must not keep a later one from distributing it
+    #[account(
+        init_if_needed,
+        payer = escrow_creator,
+        space = DistributionAudit::SPACE,
+        seeds = [b"audit".as_ref(), pool_account.key().as_ref()],
+        bump,
+    )]
+    pub audit_account: Account<'info, DistributionAudit>,
+    pub system_program: Program<'info, System>,
 }
 
 // Escrow account for quadratic funding
 #[account]
 pub struct Escrow {
     pub escrow_creator: Pubkey,
+    pub round_id: u64,
     pub creator_deposit_amount: u64,
     pub total_projects: u8,
     pub project_reciever_addresses: Vec<Pubkey>,
+    pub refunded_amount: u64,
+    pub distributed: bool,
 }
 
 // Pool for each project 
 #[account]
 pub struct Pool {
     pub pool_creator: Pubkey,
+    // Tells apart the rounds of the same creator, part of the pool's address
+    pub round_id: u64,
+    // Last slot contributions are accepted in
+    pub end_slot: u64,
     pub projects: Vec<Pubkey>,
     pub total_projects: u8,
     pub total_votes: u64,
+    // None for an open round, Some for an invite-only round
+    pub contributor_allowlist: Option<Vec<Pubkey>>,
+    pub remainder_recipient: RemainderRecipient,
+    // Rounding remainders set aside for the next round
+    pub carryover_amount: u64,
+    // Smallest amount a single contribution may carry
+    pub min_contribution: u64,
+    // Extra weight, in basis points, of contributions made by the deadline slot
+    pub early_boost_bps: u16,
+    pub early_boost_deadline_slot: u64,
+    // Most a single project may be allocated, None for no cap
+    pub max_match_per_project: Option<u64>,
+    // Decimals of the contributed token, see normalize_contribution
+    pub contribution_decimals: u8,
+    // Pool of the next round carryover_amount moves to, which also takes the
+    // deposit of a round that ends without an eligible project
+    pub carryover_to: Option<Pubkey>,
+    // Set once the round's escrow is distributed, contributions are closed for good
+    pub distributed: bool,
+}
+
+impl Pool {
+    // Owners of the pool's projects, sorted, each listed once
+    fn distinct_projects(&self) -> Vec<Pubkey> {
+        let mut projects = self.projects.clone();
+        projects.sort();
+        projects.dedup();
+        projects
+    }
+}
+
+// Contributions are brought to this many decimals before the square root is taken
+pub const NORMALIZED_DECIMALS: u8 = 6;
+
+// Largest token decimals a pool accepts, 10^18 still fits a u64
+pub const MAX_CONTRIBUTION_DECIMALS: u8 = 18;
+
+// Upper bound on projects per distribution so the audit record fits its account
+pub const MAX_AUDITED_PROJECTS: usize = 16;
+
+// Upper bound on contributions per contribute_batch call so a batch stays
+// within one transaction's compute budget
+pub const MAX_BATCH_CONTRIBUTIONS: usize = 16;
+
+// Layout version of DistributionAudit, bump when the layout changes
+pub const AUDIT_VERSION: u8 = 1;
+
+// Per-project scores and payouts of a distribution, kept for off-chain verification
+#[account]
+pub struct DistributionAudit {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub total_weight: u128,
+    pub entries: Vec<AuditEntry>,
+}
+
+impl DistributionAudit {
+    pub const SPACE: usize = 8 + 1 + 32 + 16 + 4 + MAX_AUDITED_PROJECTS * AuditEntry::SPACE;
+
+    fn record(&mut self, pool: Pubkey, total_weight: u128, projects: &[Account<Project>]) {
+        self.version = AUDIT_VERSION;
+        self.pool = pool;
+        self.total_weight = total_weight;
+        self.entries = projects
+            .iter()
+            .map(|project| AuditEntry {
+                project: project.key(),
+                score: project.match_score,
+                payout: project.distributed_amt,
+            })
+            .collect();
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AuditEntry {
+    pub project: Pubkey,
+    pub score: u128,
+    pub payout: u64,
+}
+
+impl AuditEntry {
+    pub const SPACE: usize = 32 + 16 + 8;
+}
+
+// Where the rounding remainder of a distribution goes
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RemainderRecipient {
+    Creator,
+    TopProject,
+    Carryover,
 }
 
 // Projects in each pool
@@ -199,9 +850,37 @@ pub struct Pool {
 pub struct Project {
     pub project_owner: Pubkey,
     pub project_name: String,
+    // Distinct contributors, a repeat contributor is only counted once
     pub votes_count: u64,
     pub voter_amount: u64,
     pub distributed_amt: u64,
+    // One entry per contributor holding their summed amount, the account
+    // grows with it, see fit_account
+    pub contributions: Vec<Contribution>,
+    pub match_score: u128,
+}
+
+// Raw total and quadratic weight of a project side by side
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ProjectStats {
+    pub raw_total: u64,
+    pub distinct_donors: u32,
+    pub weight: u128,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Contribution {
+    pub contributor: Pubkey,
+    pub amount: u64,
+    // Amount the score is taken over, the amount plus any early boost
+    pub weight_amount: u64,
+}
+
+// One project's share of a contribute_batch
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BatchContribution {
+    pub project: Pubkey,
+    pub amount: u64,
 }
 
 // Voters voting for the project
@@ -210,4 +889,644 @@ pub struct Voter {
     pub voter: Pubkey,
     pub voted_for: Pubkey,
     pub token_amount: u64
-}
\ No newline at end of file
+}
+
+#[event]
+pub struct NoEligibleProjects {
+    pub escrow: Pubkey,
+    pub pool: Pubkey,
+    pub amount: u64,
+    pub refunded: bool,
+}
+
+// Reject contributions once the round has ended or been distributed, or from
+// outside the allowlist
+fn check_contributor(pool_account: &Pool, voter: &Pubkey, slot: u64) -> Result<()> {
+    require!(!pool_account.distributed, ErrorCode::AlreadyDistributed);
+    require!(
+        slot <= pool_account.end_slot,
+        ErrorCode::RoundEnded
+    );
+
+    if let Some(allowlist) = &pool_account.contributor_allowlist {
+        require!(allowlist.contains(voter), ErrorCode::ContributorNotAllowed);
+    }
+
+    Ok(())
+}
+
+// Reject a project that isn't in the pool or not at the address derived for it
+fn validate_pool_project(
+    program_id: &Pubkey,
+    pool_account: &Account<Pool>,
+    project_account: &Account<Project>,
+) -> Result<()> {
+    require!(
+        pool_account.projects.contains(&project_account.project_owner),
+        ErrorCode::ProjectNotInPool
+    );
+    require_keys_eq!(
+        project_account.key(),
+        project_pda(&pool_account.key(), &project_account.project_owner, program_id).0,
+        ErrorCode::ProjectNotInPool
+    );
+
+    Ok(())
+}
+
+// Add a validated contribution to the project and refresh its score
+fn record_contribution(
+    pool_account: &mut Pool,
+    project_account: &mut Project,
+    voter: Pubkey,
+    amount: u64,
+    slot: u64,
+) -> Result<()> {
+    require!(
+        amount >= pool_account.min_contribution,
+        ErrorCode::ContributionTooSmall
+    );
+
+    let normalized = normalize_contribution(amount, pool_account.contribution_decimals)?;
+    // Contributions up to the deadline weigh extra, the amount itself is unchanged
+    let weight_amount = if slot <= pool_account.early_boost_deadline_slot {
+        normalized
+            .checked_add(mul_div(normalized, pool_account.early_boost_bps.into(), 10_000)?)
+            .ok_or(ErrorCode::MathOverflow)?
+    } else {
+        normalized
+    };
+
+    // Repeat contributions are summed into the contributor's existing entry
+    // so the square root is taken over their total
+    match project_account
+        .contributions
+        .iter_mut()
+        .find(|contribution| contribution.contributor == voter)
+    {
+        Some(contribution) => {
+            contribution.amount = contribution
+                .amount
+                .checked_add(amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+            contribution.weight_amount = contribution
+                .weight_amount
+                .checked_add(weight_amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+        None => {
+            project_account.contributions.push(Contribution {
+                contributor: voter,
+                amount,
+                weight_amount,
+            });
+            project_account.votes_count += 1;
+            pool_account.total_votes += 1;
+        }
+    }
+
+    project_account.voter_amount = project_account
+        .voter_amount
+        .checked_add(amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    project_account.match_score = match_score(&project_account.contributions);
+
+    Ok(())
+}
+
+// Grow an account once its data outgrows it, the payer covers the rent of
+// the extra space
+fn fit_account<'info, T>(
+    account: &Account<'info, T>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()>
+where
+    T: AccountSerialize + AccountDeserialize + AnchorSerialize + Owner + Clone,
+{
+    let account_info = account.to_account_info();
+    let needed = 8 + account.try_to_vec()?.len();
+    if needed <= account_info.data_len() {
+        return Ok(());
+    }
+
+    let rent = Rent::get()?
+        .minimum_balance(needed)
+        .saturating_sub(account_info.lamports());
+    if rent > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                system_program::Transfer {
+                    from: payer.to_account_info(),
+                    to: account_info.clone(),
+                },
+            ),
+            rent,
+        )?;
+    }
+
+    account_info.realloc(needed, false)?;
+    Ok(())
+}
+
+// Load the pool's projects from remaining_accounts, sorted by address. Each
+// distinct project in the pool must be passed exactly once, at the address
+// derived for this pool.
+fn load_pool_projects<'info>(
+    program_id: &Pubkey,
+    pool_key: &Pubkey,
+    pool_account: &Pool,
+    remaining_accounts: &'info [AccountInfo<'info>],
+) -> Result<Vec<Account<'info, Project>>> {
+    let pool_projects = pool_account.distinct_projects();
+
+    require!(
+        remaining_accounts.len() == pool_projects.len(),
+        ErrorCode::ProjectAccountsMismatch
+    );
+
+    let mut projects: Vec<Account<'info, Project>> = Vec::with_capacity(remaining_accounts.len());
+    for account_info in remaining_accounts {
+        let project = Account::<Project>::try_from(account_info)?;
+        let (expected, _) = project_pda(pool_key, &project.project_owner, program_id);
+
+        require_keys_eq!(account_info.key(), expected, ErrorCode::ProjectNotInPool);
+        require!(
+            pool_projects.contains(&project.project_owner),
+            ErrorCode::ProjectNotInPool
+        );
+        require!(
+            !projects.iter().any(|p| p.project_owner == project.project_owner),
+            ErrorCode::ProjectAccountsMismatch
+        );
+
+        projects.push(project);
+    }
+
+    // Canonical order, so the distribution and its audit record don't depend
+    // on the order the caller passed the projects in
+    projects.sort_by_key(|project| project.key());
+
+    Ok(projects)
+}
+
+// Reject a pool account that doesn't sit at the address derived for it
+fn validate_pool_address(pool_account: &Account<Pool>, program_id: &Pubkey) -> Result<()> {
+    let (expected, _) = pool_pda(&pool_account.pool_creator, pool_account.round_id, program_id);
+    require_keys_eq!(pool_account.key(), expected, ErrorCode::PoolAddressMismatch);
+    Ok(())
+}
+
+// Reset a project's cached counters to what its contributions say, returning
+// whether any of them was off
+fn repair_project(project: &mut Project) -> Result<bool> {
+    let votes_count = project.contributions.len() as u64;
+    let voter_amount = project
+        .contributions
+        .iter()
+        .try_fold(0u64, |total, contribution| total.checked_add(contribution.amount))
+        .ok_or(ErrorCode::MathOverflow)?;
+    let score = match_score(&project.contributions);
+
+    let repaired = project.votes_count != votes_count
+        || project.voter_amount != voter_amount
+        || project.match_score != score;
+
+    project.votes_count = votes_count;
+    project.voter_amount = voter_amount;
+    project.match_score = score;
+
+    Ok(repaired)
+}
+
+// Address of a creator's pool for the given round
+pub fn pool_pda(creator: &Pubkey, round_id: u64, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"pool".as_ref(), creator.as_ref(), round_id.to_le_bytes().as_ref()],
+        program_id,
+    )
+}
+
+// Address of a project owner's project in the given pool
+pub fn project_pda(pool: &Pubkey, project_owner: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"project".as_ref(), pool.as_ref(), project_owner.as_ref()],
+        program_id,
+    )
+}
+
+// Quadratic funding score, (sum of sqrt(contribution))^2 over distinct contributors
+pub fn match_score(contributions: &[Contribution]) -> u128 {
+    let sqrt_sum: u128 = contributions
+        .iter()
+        .map(|contribution| isqrt(contribution.weight_amount) as u128)
+        .sum();
+    sqrt_sum * sqrt_sum
+}
+
+// value * numerator / denominator, exact over the full range. The product is
+// taken in u128 and, once a weight past 2^64 makes it overflow, in 192 bits,
+// so only a quotient that doesn't fit a u64 is an error
+pub fn mul_div(value: u64, numerator: u128, denominator: u128) -> Result<u64> {
+    require!(denominator > 0, ErrorCode::MathOverflow);
+    let quotient = match (value as u128).checked_mul(numerator) {
+        Some(product) => product / denominator,
+        None => wide_mul_div(value, numerator, denominator).ok_or(ErrorCode::MathOverflow)?,
+    };
+    Ok(u64::try_from(quotient).map_err(|_| ErrorCode::MathOverflow)?)
+}
+
+// Long division of the 192-bit product value * numerator by the denominator,
+// None once the quotient no longer fits a u64
+fn wide_mul_div(value: u64, numerator: u128, denominator: u128) -> Option<u128> {
+    // The product as upper and lower 128-bit halves
+    let low = (value as u128) * (numerator as u64 as u128);
+    let high = (value as u128) * (numerator >> 64);
+    let (lower, carry) = low.overflowing_add(high << 64);
+    let upper = (high >> 64) + carry as u128;
+
+    let mut quotient: u128 = 0;
+    let mut remainder: u128 = 0;
+    for bit in (0..192).rev() {
+        let next = if bit >= 128 {
+            (upper >> (bit - 128)) & 1
+        } else {
+            (lower >> bit) & 1
+        };
+        // A remainder shifted past 128 bits is above any denominator
+        let carried = remainder >> 127 == 1;
+        remainder = (remainder << 1) | next;
+        quotient <<= 1;
+        if carried || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            quotient |= 1;
+        }
+        if quotient > u64::MAX as u128 {
+            return None;
+        }
+    }
+    Some(quotient)
+}
+
+// Splits the deposit by score without giving any project more than the cap.
+// What a capped project can't take is split again over the projects still
+// under it, until no share is clipped or no such project is left
+pub fn allocate_matching(deposit: u64, scores: &[u128], cap: u64) -> Result<Vec<u64>> {
+    let mut allocations = vec![0u64; scores.len()];
+    let mut remaining = deposit;
+
+    while remaining > 0 {
+        let open_weight = scores
+            .iter()
+            .zip(&allocations)
+            .filter(|(_, allocation)| **allocation < cap)
+            .try_fold(0u128, |total, (score, _)| total.checked_add(*score))
+            .ok_or(ErrorCode::MathOverflow)?;
+        if open_weight == 0 {
+            break;
+        }
+
+        let mut allocated: u64 = 0;
+        let mut clipped = false;
+        for (score, allocation) in scores.iter().zip(allocations.iter_mut()) {
+            if *allocation >= cap {
+                continue;
+            }
+            let share = mul_div(remaining, *score, open_weight)?;
+            let headroom = cap - *allocation;
+            clipped |= share > headroom;
+            let share = share.min(headroom);
+            *allocation += share;
+            allocated = allocated.checked_add(share).ok_or(ErrorCode::MathOverflow)?;
+        }
+
+        remaining -= allocated;
+        // Without a clipped share what is left is only rounding
+        if !clipped || allocated == 0 {
+            break;
+        }
+    }
+
+    Ok(allocations)
+}
+
+// Rescales a base-unit amount of a token with the given decimals to
+// NORMALIZED_DECIMALS, so the same economic contribution gets the same weight
+// whichever token it is made in. Digits below the normalized precision are
+// dropped, a token with fewer decimals is scaled up.
+pub fn normalize_contribution(amount: u64, decimals: u8) -> Result<u64> {
+    let normalized = if decimals >= NORMALIZED_DECIMALS {
+        amount / 10u64.pow((decimals - NORMALIZED_DECIMALS).into())
+    } else {
+        amount
+            .checked_mul(10u64.pow((NORMALIZED_DECIMALS - decimals).into()))
+            .ok_or(ErrorCode::MathOverflow)?
+    };
+    Ok(normalized)
+}
+
+// Integer square root, rounded down
+pub fn isqrt(n: u64) -> u64 {
+    if n < 2 {
+        return n;
+    }
+
+    let mut x = n / 2 + 1;
+    let mut y = (x + n / x) / 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Contributor is not on the pool's allowlist")]
+    ContributorNotAllowed,
+    #[msg("Project is not part of this pool")]
+    ProjectNotInPool,
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
+    #[msg("Each project in the pool must be passed exactly once")]
+    ProjectAccountsMismatch,
+    #[msg("The escrow has already been distributed")]
+    AlreadyDistributed,
+    #[msg("Too many projects to distribute in one round")]
+    TooManyProjects,
+    #[msg("No project in the pool has any matching weight")]
+    NothingToDistribute,
+    #[msg("Pool account is not at the address derived for its creator and round")]
+    PoolAddressMismatch,
+    #[msg("The round's contribution window has ended")]
+    RoundEnded,
+    #[msg("A round can only be extended to a later end slot")]
+    EndSlotNotLater,
+    #[msg("The escrow and the pool belong to different rounds")]
+    RoundMismatch,
+    #[msg("Contribution is below the pool's minimum")]
+    ContributionTooSmall,
+    #[msg("Token decimals are above the supported maximum")]
+    InvalidContributionDecimals,
+    #[msg("The round already has contributions")]
+    RoundAlreadyStarted,
+    #[msg("The pool doesn't carry over to this pool")]
+    CarryoverTargetMismatch,
+    #[msg("Too many contributions in one batch")]
+    TooManyContributions,
+    #[msg("The round's contribution window is still open")]
+    RoundNotEnded,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_pda_is_stable_and_distinct_per_creator_and_round() {
+        let creator = Pubkey::new_unique();
+        let other_creator = Pubkey::new_unique();
+
+        assert_eq!(pool_pda(&creator, 0, &ID), pool_pda(&creator, 0, &ID));
+        assert_ne!(pool_pda(&creator, 0, &ID).0, pool_pda(&creator, 1, &ID).0);
+        assert_ne!(pool_pda(&creator, 0, &ID).0, pool_pda(&other_creator, 0, &ID).0);
+        assert_ne!(pool_pda(&creator, 0, &ID).0, pool_pda(&creator, 0, &Pubkey::new_unique()).0);
+    }
+
+    #[test]
+    fn project_pda_is_stable_and_distinct_per_pool_and_owner() {
+        let (pool, _) = pool_pda(&Pubkey::new_unique(), 0, &ID);
+        let (other_pool, _) = pool_pda(&Pubkey::new_unique(), 0, &ID);
+        let owner = Pubkey::new_unique();
+        let other_owner = Pubkey::new_unique();
+
+        assert_eq!(project_pda(&pool, &owner, &ID), project_pda(&pool, &owner, &ID));
+        assert_ne!(project_pda(&pool, &owner, &ID).0, project_pda(&pool, &other_owner, &ID).0);
+        assert_ne!(project_pda(&pool, &owner, &ID).0, project_pda(&other_pool, &owner, &ID).0);
+        assert_ne!(project_pda(&pool, &owner, &ID).0, pool);
+    }
+
+    #[test]
+    fn repair_project_restores_corrupted_counters() {
+        let mut project = Project {
+            project_owner: Pubkey::new_unique(),
+            project_name: "Repaired".to_string(),
+            // Two contributors, one of them counted twice
+            votes_count: 3,
+            voter_amount: 25,
+            distributed_amt: 0,
+            contributions: vec![
+                Contribution {
+                    contributor: Pubkey::new_unique(),
+                    amount: 16,
+                    weight_amount: 16,
+                },
+                Contribution {
+                    contributor: Pubkey::new_unique(),
+                    amount: 9,
+                    weight_amount: 9,
+                },
+            ],
+            match_score: 0,
+        };
+
+        assert!(repair_project(&mut project).unwrap());
+        assert_eq!(project.votes_count, 2);
+        assert_eq!(project.voter_amount, 25);
+        assert_eq!(project.match_score, 49);
+
+        // A consistent project is left as is
+        assert!(!repair_project(&mut project).unwrap());
+    }
+
+    // Seed of the generated quadratic funding cases, a failure prints the case
+    // seed so it can be replayed with PropertyRng::new(case_seed)
+    const PROPERTY_SEED: u64 = 0x5eed_0f9f_2024;
+    const PROPERTY_CASES: u64 = 4_000;
+
+    // xorshift64*, deterministic and dependency free
+    struct PropertyRng(u64);
+
+    impl PropertyRng {
+        fn new(seed: u64) -> Self {
+            PropertyRng(seed.max(1))
+        }
+
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        // Uniform enough in 0..bound for test inputs
+        fn below(&mut self, bound: u64) -> u64 {
+            self.next() % bound
+        }
+
+        // Amounts spread over every magnitude up to u64::MAX, so deposit *
+        // weight also takes mul_div past u128
+        fn amount(&mut self) -> u64 {
+            self.next() >> self.below(64)
+        }
+
+        fn contributions(&mut self) -> Vec<Contribution> {
+            (0..self.below(8))
+                .map(|_| {
+                    let amount = self.amount();
+                    Contribution {
+                        contributor: Pubkey::new_from_array([0; 32]),
+                        amount,
+                        weight_amount: amount,
+                    }
+                })
+                .collect()
+        }
+    }
+
+    fn for_each_case(mut check: impl FnMut(u64, &mut PropertyRng)) {
+        for case in 0..PROPERTY_CASES {
+            let case_seed = PROPERTY_SEED.wrapping_add(case);
+            check(case_seed, &mut PropertyRng::new(case_seed));
+        }
+    }
+
+    #[test]
+    fn isqrt_rounds_down() {
+        for_each_case(|seed, rng| {
+            let n = rng.next() >> rng.below(64);
+            let root = isqrt(n) as u128;
+            assert!(
+                root * root <= n as u128 && (root + 1) * (root + 1) > n as u128,
+                "seed {seed}: isqrt({n}) = {root}"
+            );
+        });
+    }
+
+    #[test]
+    fn match_score_never_drops_when_a_contributor_joins() {
+        for_each_case(|seed, rng| {
+            let mut contributions = rng.contributions();
+            let before = match_score(&contributions);
+            let joining = rng.amount();
+            contributions.push(Contribution {
+                contributor: Pubkey::new_from_array([1; 32]),
+                amount: joining,
+                weight_amount: joining,
+            });
+
+            assert!(
+                match_score(&contributions) >= before,
+                "seed {seed}: adding {joining} to {:?} lowered the score",
+                contributions.iter().map(|c| c.amount).collect::<Vec<_>>()
+            );
+        });
+    }
+
+    #[test]
+    fn distribution_conserves_the_deposit() {
+        for_each_case(|seed, rng| {
+            let deposit = rng.next() >> rng.below(64);
+            let scores: Vec<u128> = (0..1 + rng.below(MAX_AUDITED_PROJECTS as u64))
+                .map(|_| match_score(&rng.contributions()))
+                .collect();
+            let total_weight: u128 = scores.iter().sum();
+            if total_weight == 0 {
+                return;
+            }
+
+            let payouts = allocate_matching(deposit, &scores, u64::MAX).unwrap();
+            let distributed: u64 = payouts.iter().sum();
+
+            // Each payout is rounded down by less than one unit
+            assert!(
+                distributed <= deposit && deposit - distributed < scores.len() as u64,
+                "seed {seed}: deposit {deposit}, scores {scores:?}, payouts {payouts:?}"
+            );
+            for (i, j) in (0..scores.len()).flat_map(|i| (0..scores.len()).map(move |j| (i, j))) {
+                assert!(
+                    scores[i] < scores[j] || payouts[i] >= payouts[j],
+                    "seed {seed}: scores {scores:?} paid out {payouts:?}"
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn capped_project_overflow_flows_to_the_others() {
+        // Weights 8:1:1 of 1000 would give the first project 800
+        let allocations = allocate_matching(1_000, &[8, 1, 1], 500).unwrap();
+        assert_eq!(allocations, vec![500, 250, 250]);
+
+        // Once every project is at the cap the rest stays unallocated
+        let allocations = allocate_matching(1_000, &[8, 1, 1], 300).unwrap();
+        assert_eq!(allocations, vec![300, 300, 300]);
+
+        // Without a cap the split is plainly proportional
+        let allocations = allocate_matching(1_000, &[8, 1, 1], u64::MAX).unwrap();
+        assert_eq!(allocations, vec![800, 100, 100]);
+    }
+
+    #[test]
+    fn mul_div_stays_exact_past_u128_products() {
+        assert_eq!(mul_div(u64::MAX, u128::MAX, u128::MAX).unwrap(), u64::MAX);
+        assert_eq!(mul_div(u64::MAX, 3 << 100, 3 << 101).unwrap(), u64::MAX / 2);
+        assert_eq!(mul_div(1 << 63, 1 << 120, 1 << 122).unwrap(), 1 << 61);
+        assert!(mul_div(u64::MAX, u128::MAX, u128::MAX / 2).is_err());
+        assert!(mul_div(1, 1, 0).is_err());
+    }
+
+    #[test]
+    fn allocation_handles_u64_max_contributions() {
+        // Two contributors at u64::MAX give each project a weight of about 2^66
+        let contribution = |seed| Contribution {
+            contributor: Pubkey::new_from_array([seed; 32]),
+            amount: u64::MAX,
+            weight_amount: u64::MAX,
+        };
+        let score = match_score(&[contribution(1), contribution(2)]);
+        assert!(score > u64::MAX as u128);
+
+        let deposit = 1_000_000_000_000_000_000;
+        let scores = vec![score; MAX_AUDITED_PROJECTS];
+        let allocations = allocate_matching(deposit, &scores, u64::MAX).unwrap();
+        assert_eq!(allocations, vec![deposit / MAX_AUDITED_PROJECTS as u64; MAX_AUDITED_PROJECTS]);
+
+        let allocations = allocate_matching(u64::MAX, &[score, 1], u64::MAX).unwrap();
+        assert_eq!(allocations[1], 0);
+        assert!(u64::MAX - allocations[0] <= 1);
+    }
+
+    #[test]
+    fn equal_contributions_in_tokens_of_different_decimals_weigh_the_same() {
+        // 25 whole tokens of a 6 and of a 9 decimal token
+        let score = |amount, decimals| {
+            let weight_amount = normalize_contribution(amount, decimals).unwrap();
+            match_score(&[Contribution {
+                contributor: Pubkey::new_unique(),
+                amount,
+                weight_amount,
+            }])
+        };
+        assert_eq!(score(25_000_000, 6), score(25_000_000_000, 9));
+        assert_eq!(score(25, 0), score(25_000_000, 6));
+        assert_eq!(score(25_000_000_000, 9), 25_000_000);
+
+        // Below the normalized precision a high-decimal token rounds down
+        assert_eq!(normalize_contribution(999, 9).unwrap(), 0);
+        assert!(normalize_contribution(u64::MAX, 0).is_err());
+    }
+
+    #[test]
+    fn pool_pda_matches_the_initialize_seeds() {
+        let creator = Pubkey::new_unique();
+        let round_id: u64 = 7;
+        let (expected, _) = Pubkey::find_program_address(
+            &[b"pool", creator.as_ref(), &7u64.to_le_bytes()],
+            &ID,
+        );
+
+        assert_eq!(pool_pda(&creator, round_id, &ID).0, expected);
+    }
+}
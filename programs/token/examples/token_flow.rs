@@ -0,0 +1,137 @@
+// Drives the token program through initialize, transfer, approve and
+// transfer_from by calling process_instruction directly, then reads every
+// balance back from return data the way a client reads a transaction's.
+//
+//     cargo run -p solquad-token --example token_flow
+
+use std::sync::Mutex;
+
+use solana_program::{
+    account_info::AccountInfo,
+    program::get_return_data,
+    program_stubs::{self, SyscallStubs},
+    pubkey::Pubkey,
+};
+use solquad_token::{process_instruction, TokenInstruction};
+
+const PROGRAM_ID: Pubkey = Pubkey::new_from_array([7; 32]);
+
+// Return data of the last instruction, what the runtime hands back to the caller
+static RETURN_DATA: Mutex<Option<(Pubkey, Vec<u8>)>> = Mutex::new(None);
+
+struct ReturnDataStubs;
+
+impl SyscallStubs for ReturnDataStubs {
+    fn sol_set_return_data(&self, data: &[u8]) {
+        *RETURN_DATA.lock().unwrap() = Some((PROGRAM_ID, data.to_vec()));
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        RETURN_DATA.lock().unwrap().clone()
+    }
+}
+
+fn run(accounts: &[AccountInfo], instruction: TokenInstruction) {
+    let data = instruction.pack();
+    if let Err(err) = process_instruction(&PROGRAM_ID, accounts, &data) {
+        panic!("{instruction:?} failed: {err}");
+    }
+}
+
+fn signing<'a>(account: &AccountInfo<'a>) -> AccountInfo<'a> {
+    let mut account = account.clone();
+    account.is_signer = true;
+    account
+}
+
+fn balance_of<'a>(state: &AccountInfo<'a>, account: &AccountInfo<'a>) -> u64 {
+    run(
+        &[state.clone(), account.clone()],
+        TokenInstruction::GetBalance { strict: false },
+    );
+    let (program_id, data) = get_return_data().expect("GetBalance writes return data");
+    assert_eq!(program_id, PROGRAM_ID);
+    u64::from_le_bytes(data.try_into().expect("a little-endian u64"))
+}
+
+fn main() {
+    program_stubs::set_syscall_stubs(Box::new(ReturnDataStubs));
+
+    // The state account holds the token and, once initialized, owns the supply
+    let state_key = Pubkey::new_unique();
+    let (mut state_lamports, mut state_data) = (0, vec![0; 1024]);
+    let state = AccountInfo::new(
+        &state_key,
+        false,
+        true,
+        &mut state_lamports,
+        &mut state_data,
+        &PROGRAM_ID,
+        false,
+        0,
+    );
+
+    let keys: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+    let mut lamports = vec![0; keys.len()];
+    let system_program = Pubkey::default();
+    let wallets: Vec<AccountInfo> = keys
+        .iter()
+        .zip(lamports.iter_mut())
+        .map(|(key, lamports)| {
+            AccountInfo::new(
+                key,
+                false,
+                false,
+                lamports,
+                &mut [],
+                &system_program,
+                false,
+                0,
+            )
+        })
+        .collect();
+    let [alice, bob, carol] = [&wallets[0], &wallets[1], &wallets[2]];
+
+    run(
+        &[signing(&state)],
+        TokenInstruction::Initialize {
+            total_supply: 1_000,
+        },
+    );
+
+    run(
+        &[state.clone(), signing(&state), alice.clone()],
+        TokenInstruction::Transfer { amount: 300 },
+    );
+
+    run(
+        &[state.clone(), signing(alice)],
+        TokenInstruction::Approve {
+            spender: *bob.key,
+            amount: 100,
+        },
+    );
+
+    // Bob spends 60 of Alice's allowance on a payment to Carol
+    run(
+        &[state.clone(), signing(bob), alice.clone(), carol.clone()],
+        TokenInstruction::TransferFrom { amount: 60 },
+    );
+
+    let balances: Vec<(&str, u64)> = [
+        ("owner", &state),
+        ("alice", alice),
+        ("bob", bob),
+        ("carol", carol),
+    ]
+    .into_iter()
+    .map(|(name, account)| (name, balance_of(&state, account)))
+    .collect();
+    for (name, balance) in &balances {
+        println!("{name}: {balance}");
+    }
+    assert_eq!(
+        balances,
+        vec![("owner", 700), ("alice", 240), ("bob", 0), ("carol", 60)]
+    );
+}
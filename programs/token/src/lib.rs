@@ -0,0 +1,2983 @@
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint,
+    entrypoint::ProgramResult,
+    msg,
+    program::{set_return_data, MAX_RETURN_DATA},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
+};
+
+#[cfg(not(feature = "no-entrypoint"))]
+entrypoint!(process_instruction);
+
+// Every integer in instruction data is encoded little-endian, a u64 amount of 1
+// is sent as 01 00 00 00 00 00 00 00. Clients in other languages must match.
+pub const ENDIANNESS: &str = "little";
+
+// Define the instruction data structure
+#[derive(Debug, PartialEq)]
+pub enum TokenInstruction {
+    // Initialize the token with the specified total supply, once. The state
+    // account becomes the owner and must sign
+    Initialize { total_supply: u64 },
+    // Transfer tokens from the sender to the specified recipient
+    Transfer { amount: u64 },
+    // Get the token balance of the specified account. Accounts without an entry
+    // read as 0 unless strict is set, then they fail with RecipientNotFound.
+    GetBalance { strict: bool },
+    // Approve a spender to spend tokens on behalf of the sender. The spender
+    // only needs to be known, not to sign, so it is read from the data rather
+    // than passed as an account. Accounts: state, owner (signer)
+    Approve { spender: Pubkey, amount: u64 },
+    // Transfer tokens from an owner to a recipient using the signer's allowance
+    TransferFrom { amount: u64 },
+    // Report whether the state account holds an initialized token
+    GetInitialized,
+    // Get the number of nonzero allowances across all owners
+    GetActiveAllowanceCount,
+    // Get the token balances of up to MAX_BALANCES_QUERY accounts
+    GetBalances { accounts: Vec<Pubkey> },
+    // Drop zeroed allowances to free slots for new ones
+    PruneAllowances,
+    // Block an account from sending or receiving, owner only
+    FreezeAccount { account: Pubkey },
+    // Lift a freeze, owner only
+    ThawAccount { account: Pubkey },
+    // Get the owner's balance, the (owner, spender) allowance and the owner's frozen flag
+    GetAccountSummary { owner: Pubkey, spender: Pubkey },
+    // Turn per-account transfer counting on or off, owner only
+    SetTransferTracking { enabled: bool },
+    // Get how many transfers an account has sent or received while tracking was on
+    GetTransferCount { account: Pubkey },
+    // Transfer like Transfer, but only while the current slot is at or before deadline_slot
+    TransferWithDeadline { amount: u64, deadline_slot: u64 },
+    // Reset total_supply to the sum of all balances to repair drifted state, owner only
+    RecomputeSupply,
+    // Get up to count of the largest balances, at most MAX_TOP_HOLDERS_QUERY
+    GetTopHolders { count: u32 },
+    // Move lamports sent to the state account beyond its rent-exempt reserve
+    // to destination, owner only
+    WithdrawExcess { amount: u64, destination: Pubkey },
+    // Cap the number of balance entries, None lifts the cap, owner only
+    SetMaxHolders { max_holders: Option<u64> },
+    // Get the layout version of the state account without unpacking the rest
+    GetAccountVersion,
+    // Raise the signer's allowance for a spender by amount
+    IncreaseAllowance { spender: Pubkey, amount: u64 },
+    // Lower the signer's allowance for a spender by amount
+    DecreaseAllowance { spender: Pubkey, amount: u64 },
+    // Zero the signer's allowance for a spender
+    Revoke { spender: Pubkey },
+    // Destroy amount of the signer's own tokens, lowering total_supply with them
+    Burn { amount: u64 },
+    // Turn the full freeze on or off, owner only and the one instruction it lets through
+    SetFullFreeze { enabled: bool },
+    // Pause or resume every instruction that writes state, reads keep working, owner only
+    SetPaused { paused: bool },
+}
+
+// Upper bound on the accounts a single GetBalances query may ask for
+pub const MAX_BALANCES_QUERY: usize = 64;
+
+// Upper bound on the holders a single GetTopHolders query may ask for, so the
+// response still fits the runtime's return data
+pub const MAX_TOP_HOLDERS_QUERY: usize = (MAX_RETURN_DATA - 4) / Token::BALANCE_LEN;
+
+// Upper bound on stored allowances so the state keeps fitting in its account
+pub const MAX_ALLOWANCES: usize = 32;
+
+// Define the program specific errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenError {
+    // The spender's allowance does not cover the requested amount
+    InsufficientAllowance,
+    // An arithmetic operation would exceed u64::MAX
+    Overflow,
+    // A batched query asked for more entries than allowed
+    BatchTooLarge,
+    // No free allowance slot is left, zeroed allowances can be pruned
+    AllowanceLimitReached,
+    // The signer is not the token owner
+    NotOwner,
+    // The account is frozen and can't send or receive
+    AccountFrozen,
+    // The current slot is past the transaction's deadline
+    TransactionExpired,
+    // The account has no balance entry
+    RecipientNotFound,
+    // A new holder would exceed max_holders
+    HolderLimitReached,
+    // The unpacked balances don't add up to total_supply
+    CorruptState,
+    // The spender is the default (all-zeros) pubkey
+    InvalidSpender,
+    // The token is under a full freeze, reads included
+    FullyFrozen,
+    // The token is paused, only reads are served
+    Paused,
+}
+
+impl From<TokenError> for ProgramError {
+    fn from(e: TokenError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+// Prefix written at the start of every packed token account
+pub const TOKEN_MAGIC: [u8; 4] = *b"SQTK";
+
+// Layout version written right after the magic, bump when the layout changes
+pub const TOKEN_LAYOUT_VERSION: u8 = 2;
+
+// Oldest layout still read. Version 1 lacks the flags byte and unpacks with
+// every flag off, the next write stores it at the current version.
+pub const MIN_TOKEN_LAYOUT_VERSION: u8 = 1;
+
+// The flags byte sits right after the version, so the instruction gate reads
+// it without unpacking the rest of the state
+const FLAGS_OFFSET: usize = TOKEN_MAGIC.len() + 1;
+
+// Bits of the flags byte
+const FLAG_FULL_FREEZE: u8 = 1 << 0;
+const FLAG_PAUSED: u8 = 1 << 1;
+
+// Define the token state
+pub struct Token {
+    pub total_supply: u64,
+    pub owner: Pubkey,
+    pub balances: Vec<(Pubkey, u64)>,
+    pub allowances: Vec<(Pubkey, Pubkey, u64)>,
+    pub frozen: Vec<Pubkey>,
+    // Transfer counting is opt-in so tokens that don't need it keep their state small
+    pub track_transfers: bool,
+    pub transfer_counts: Vec<(Pubkey, u64)>,
+    // Upper bound on balance entries, None for no bound
+    pub max_holders: Option<u64>,
+    // Rejects every instruction but SetFullFreeze, for critical migrations
+    pub full_freeze: bool,
+    // Rejects every instruction that writes state, reads keep working
+    pub paused: bool,
+}
+
+// Result of GetAccountSummary
+#[derive(Debug, PartialEq)]
+pub struct AccountSummary {
+    pub balance: u64,
+    pub allowance: u64,
+    pub frozen: bool,
+}
+
+impl AccountSummary {
+    pub const LEN: usize = 17;
+
+    // Layout: balance u64 LE (0..8), allowance u64 LE (8..16), frozen u8 (16)
+    pub fn pack(&self) -> [u8; Self::LEN] {
+        let mut data = [0; Self::LEN];
+        data[..8].copy_from_slice(&self.balance.to_le_bytes());
+        data[8..16].copy_from_slice(&self.allowance.to_le_bytes());
+        data[16] = self.frozen as u8;
+        data
+    }
+}
+
+// How an approval changed an allowance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalKind {
+    // Approve, an absolute amount
+    Set,
+    Increase,
+    Decrease,
+    Revoke,
+}
+
+// Allowance change reported to indexers, with the allowance it left behind
+#[derive(Debug, PartialEq)]
+pub struct ApprovalEvent {
+    pub kind: ApprovalKind,
+    pub owner: Pubkey,
+    pub spender: Pubkey,
+    pub allowance: u64,
+}
+
+impl ApprovalEvent {
+    pub fn log(&self) {
+        msg!(
+            "Approval {:?}: owner {} spender {} allowance {}",
+            self.kind,
+            self.owner,
+            self.spender,
+            self.allowance
+        );
+    }
+}
+
+impl Token {
+    // Packed size of a token with no entries: magic, version, the flags byte,
+    // total_supply, owner, the four list lengths, the transfer tracking flag
+    // and max_holders
+    pub const BASE_LEN: usize = 4 + 1 + 1 + 8 + 32 + 4 + 4 + 4 + 1 + 4 + 9;
+    // Packed size of one balance entry
+    pub const BALANCE_LEN: usize = 32 + 8;
+
+    // Initialize a new token
+    pub fn initialize(&mut self, total_supply: u64, owner: Pubkey) {
+        self.total_supply = total_supply;
+        self.owner = owner;
+        self.balances.push((owner, total_supply));
+    }
+
+    // Check whether account data holds a valid packed token
+    pub fn is_initialized(data: &[u8]) -> bool {
+        Self::unpack_from_slice(data).is_ok()
+    }
+
+    // Number of bytes pack_into_slice writes
+    pub fn packed_len(&self) -> usize {
+        Self::BASE_LEN
+            + self.balances.len() * Self::BALANCE_LEN
+            + self.allowances.len() * (32 + 32 + 8)
+            + self.frozen.len() * 32
+            + self.transfer_counts.len() * (32 + 8)
+    }
+
+    // Pack the token into account data. The size is checked up front so a
+    // short account is never left half written.
+    pub fn pack_into_slice(&self, dst: &mut [u8]) -> ProgramResult {
+        if dst.len() < self.packed_len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+
+        let mut offset = 0;
+        Self::pack_bytes(dst, &mut offset, &TOKEN_MAGIC)?;
+        Self::pack_bytes(dst, &mut offset, &[TOKEN_LAYOUT_VERSION])?;
+        let flags = if self.full_freeze { FLAG_FULL_FREEZE } else { 0 }
+            | if self.paused { FLAG_PAUSED } else { 0 };
+        Self::pack_bytes(dst, &mut offset, &[flags])?;
+        Self::pack_bytes(dst, &mut offset, &self.total_supply.to_le_bytes())?;
+        Self::pack_bytes(dst, &mut offset, self.owner.as_ref())?;
+
+        Self::pack_bytes(dst, &mut offset, &(self.balances.len() as u32).to_le_bytes())?;
+        for (account, balance) in &self.balances {
+            Self::pack_bytes(dst, &mut offset, account.as_ref())?;
+            Self::pack_bytes(dst, &mut offset, &balance.to_le_bytes())?;
+        }
+
+        Self::pack_bytes(dst, &mut offset, &(self.allowances.len() as u32).to_le_bytes())?;
+        for (owner, spender, amount) in &self.allowances {
+            Self::pack_bytes(dst, &mut offset, owner.as_ref())?;
+            Self::pack_bytes(dst, &mut offset, spender.as_ref())?;
+            Self::pack_bytes(dst, &mut offset, &amount.to_le_bytes())?;
+        }
+
+        Self::pack_bytes(dst, &mut offset, &(self.frozen.len() as u32).to_le_bytes())?;
+        for account in &self.frozen {
+            Self::pack_bytes(dst, &mut offset, account.as_ref())?;
+        }
+
+        Self::pack_bytes(dst, &mut offset, &[self.track_transfers as u8])?;
+        Self::pack_bytes(dst, &mut offset, &(self.transfer_counts.len() as u32).to_le_bytes())?;
+        for (account, count) in &self.transfer_counts {
+            Self::pack_bytes(dst, &mut offset, account.as_ref())?;
+            Self::pack_bytes(dst, &mut offset, &count.to_le_bytes())?;
+        }
+
+        // A flag byte then the cap, zero when there is none
+        Self::pack_bytes(dst, &mut offset, &[self.max_holders.is_some() as u8])?;
+        Self::pack_bytes(dst, &mut offset, &self.max_holders.unwrap_or(0).to_le_bytes())?;
+
+        Ok(())
+    }
+
+    // Unpack a token from account data. Debug builds also check that the
+    // balances add up to total_supply, to catch corrupted data early.
+    pub fn unpack_from_slice(src: &[u8]) -> Result<Token, ProgramError> {
+        let token = Self::unpack_unchecked(src)?;
+        if cfg!(debug_assertions) {
+            let balances_sum = token
+                .balances
+                .iter()
+                .try_fold(0u64, |total, (_, balance)| total.checked_add(*balance));
+            if balances_sum != Some(token.total_supply) {
+                return Err(TokenError::CorruptState.into());
+            }
+        }
+        Ok(token)
+    }
+
+    // Unpack a token from account data without checking it is consistent, for
+    // repairs that must load drifted state
+    pub fn unpack_unchecked(src: &[u8]) -> Result<Token, ProgramError> {
+        let flags = Self::header_flags(src)?;
+        let header_len = match Self::layout_version(src)? {
+            1 => FLAGS_OFFSET,
+            _ => FLAGS_OFFSET + 1,
+        };
+        let (_header, rest) = Self::unpack_bytes(src, header_len)?;
+        let (total_supply, rest) = Self::unpack_u64(rest)?;
+        let (owner, rest) = Self::unpack_pubkey(rest)?;
+
+        let (balances_len, mut rest) = Self::unpack_len(rest, Self::BALANCE_LEN)?;
+        let mut balances = Vec::with_capacity(balances_len);
+        for _ in 0..balances_len {
+            let (account, next) = Self::unpack_pubkey(rest)?;
+            let (balance, next) = Self::unpack_u64(next)?;
+            balances.push((account, balance));
+            rest = next;
+        }
+
+        let (allowances_len, mut rest) = Self::unpack_len(rest, 32 + 32 + 8)?;
+        let mut allowances = Vec::with_capacity(allowances_len);
+        for _ in 0..allowances_len {
+            let (owner, next) = Self::unpack_pubkey(rest)?;
+            let (spender, next) = Self::unpack_pubkey(next)?;
+            let (amount, next) = Self::unpack_u64(next)?;
+            allowances.push((owner, spender, amount));
+            rest = next;
+        }
+
+        let (frozen_len, mut rest) = Self::unpack_len(rest, 32)?;
+        let mut frozen = Vec::with_capacity(frozen_len);
+        for _ in 0..frozen_len {
+            let (account, next) = Self::unpack_pubkey(rest)?;
+            frozen.push(account);
+            rest = next;
+        }
+
+        let (track_transfers, rest) = Self::unpack_bytes(rest, 1)?;
+        let (counts_len, mut rest) = Self::unpack_len(rest, 32 + 8)?;
+        let mut transfer_counts = Vec::with_capacity(counts_len);
+        for _ in 0..counts_len {
+            let (account, next) = Self::unpack_pubkey(rest)?;
+            let (count, next) = Self::unpack_u64(next)?;
+            transfer_counts.push((account, count));
+            rest = next;
+        }
+
+        let (has_max_holders, rest) = Self::unpack_bytes(rest, 1)?;
+        let (max_holders, _rest) = Self::unpack_u64(rest)?;
+
+        Ok(Token {
+            total_supply,
+            owner,
+            balances,
+            allowances,
+            frozen,
+            track_transfers: track_transfers[0] != 0,
+            transfer_counts,
+            max_holders: (has_max_holders[0] != 0).then_some(max_holders),
+            full_freeze: flags & FLAG_FULL_FREEZE != 0,
+            paused: flags & FLAG_PAUSED != 0,
+        })
+    }
+
+    // Whether this program can read data of the given layout version
+    pub fn is_known_layout(version: u8) -> bool {
+        (MIN_TOKEN_LAYOUT_VERSION..=TOKEN_LAYOUT_VERSION).contains(&version)
+    }
+
+    // Read only the layout version of packed account data
+    pub fn layout_version(src: &[u8]) -> Result<u8, ProgramError> {
+        let (magic, rest) = Self::unpack_bytes(src, TOKEN_MAGIC.len())?;
+        if magic != TOKEN_MAGIC {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        let (version, _rest) = Self::unpack_bytes(rest, 1)?;
+        Ok(version[0])
+    }
+
+    // Read only the flags byte of packed account data, zero for version 1
+    pub fn header_flags(src: &[u8]) -> Result<u8, ProgramError> {
+        match Self::layout_version(src)? {
+            version if !Self::is_known_layout(version) => Err(ProgramError::InvalidAccountData),
+            1 => Ok(0),
+            _ => Ok(Self::unpack_bytes(&src[FLAGS_OFFSET..], 1)?.0[0]),
+        }
+    }
+
+    fn pack_bytes(dst: &mut [u8], offset: &mut usize, bytes: &[u8]) -> ProgramResult {
+        let end = *offset + bytes.len();
+        dst.get_mut(*offset..end)
+            .ok_or(ProgramError::AccountDataTooSmall)?
+            .copy_from_slice(bytes);
+        *offset = end;
+        Ok(())
+    }
+
+    fn unpack_bytes(input: &[u8], len: usize) -> Result<(&[u8], &[u8]), ProgramError> {
+        if input.len() < len {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(input.split_at(len))
+    }
+
+    fn unpack_u32(input: &[u8]) -> Result<(u32, &[u8]), ProgramError> {
+        let (bytes, rest) = Self::unpack_bytes(input, 4)?;
+        Ok((u32::from_le_bytes(bytes.try_into().unwrap()), rest))
+    }
+
+    // Read a list length, rejecting one whose entries cannot fit in the rest
+    // of the data so a corrupt length never sizes an allocation
+    fn unpack_len(input: &[u8], entry_len: usize) -> Result<(usize, &[u8]), ProgramError> {
+        let (len, rest) = Self::unpack_u32(input)?;
+        let len = len as usize;
+        if len > rest.len() / entry_len {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok((len, rest))
+    }
+
+    fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+        let (bytes, rest) = Self::unpack_bytes(input, 8)?;
+        Ok((u64::from_le_bytes(bytes.try_into().unwrap()), rest))
+    }
+
+    fn unpack_pubkey(input: &[u8]) -> Result<(Pubkey, &[u8]), ProgramError> {
+        let (bytes, rest) = Self::unpack_bytes(input, 32)?;
+        Ok((Pubkey::new_from_array(bytes.try_into().unwrap()), rest))
+    }
+
+    // Transfer tokens from sender to recipient
+    pub fn transfer(&mut self, sender: &Pubkey, recipient: &Pubkey, amount: u64) -> ProgramResult {
+        if self.is_frozen(sender) || self.is_frozen(recipient) {
+            return Err(TokenError::AccountFrozen.into());
+        }
+
+        self.move_balance(sender, recipient, amount)
+    }
+
+    // Burn from the holder's own balance, dropping the entry once it is empty
+    pub fn burn(&mut self, holder: &Pubkey, amount: u64) -> ProgramResult {
+        if self.is_frozen(holder) {
+            return Err(TokenError::AccountFrozen.into());
+        }
+
+        let index = self
+            .balances
+            .iter()
+            .position(|(account, _)| *account == *holder)
+            .ok_or(ProgramError::InvalidArgument)?;
+        let balance = self.balances[index]
+            .1
+            .checked_sub(amount)
+            .ok_or(ProgramError::InsufficientFunds)?;
+        // Every balance is part of the supply, a supply smaller than one is corrupt
+        let total_supply = self
+            .total_supply
+            .checked_sub(amount)
+            .ok_or(TokenError::CorruptState)?;
+
+        self.total_supply = total_supply;
+        if balance == 0 {
+            self.balances.remove(index);
+        } else {
+            self.balances[index].1 = balance;
+        }
+        Ok(())
+    }
+
+    // Move tokens between two balances in a single scan, once the caller has
+    // checked the accounts may transact
+    fn move_balance(&mut self, sender: &Pubkey, recipient: &Pubkey, amount: u64) -> ProgramResult {
+        let mut sender_index = None;
+        let mut recipient_index = None;
+
+        for (i, (account, _)) in self.balances.iter().enumerate() {
+            if *account == *sender {
+                sender_index = Some(i);
+            }
+            if *account == *recipient {
+                recipient_index = Some(i);
+            }
+            if sender_index.is_some() && recipient_index.is_some() {
+                break;
+            }
+        }
+
+        let sender_index = sender_index.ok_or(ProgramError::InvalidArgument)?;
+
+        if self.balances[sender_index].1 < amount {
+            return Err(ProgramError::InsufficientFunds);
+        }
+
+        match recipient_index {
+            // Validate the credit before touching either balance so an overflow
+            // leaves the state unchanged
+            Some(recipient_index) if recipient_index != sender_index => {
+                let recipient_balance = self.balances[recipient_index]
+                    .1
+                    .checked_add(amount)
+                    .ok_or(TokenError::Overflow)?;
+                self.balances[sender_index].1 -= amount;
+                self.balances[recipient_index].1 = recipient_balance;
+            }
+            Some(_) => {}
+            // A new recipient gets an entry, as long as the holder cap allows it.
+            // Nothing moves on a zero amount, so it doesn't create one.
+            None if amount > 0 => {
+                if matches!(self.max_holders, Some(max_holders) if self.balances.len() as u64 >= max_holders) {
+                    return Err(TokenError::HolderLimitReached.into());
+                }
+                self.balances[sender_index].1 -= amount;
+                self.balances.push((*recipient, amount));
+            }
+            None => {}
+        }
+
+        if self.track_transfers {
+            self.record_transfer(sender);
+            if sender != recipient {
+                self.record_transfer(recipient);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Bump an account's transfer counter
+    fn record_transfer(&mut self, account: &Pubkey) {
+        match self.transfer_counts.iter_mut().find(|(acc, _)| *acc == *account) {
+            Some((_, count)) => *count = count.saturating_add(1),
+            None => self.transfer_counts.push((*account, 1)),
+        }
+    }
+
+    // Get how many transfers an account has sent or received while tracking was on
+    pub fn get_transfer_count(&self, account: &Pubkey) -> u64 {
+        self.transfer_counts
+            .iter()
+            .find(|(acc, _)| *acc == *account)
+            .map(|(_, count)| *count)
+            .unwrap_or(0)
+    }
+
+    // Turn transfer counting on or off on behalf of the token owner
+    pub fn set_transfer_tracking(&mut self, authority: &Pubkey, enabled: bool) -> ProgramResult {
+        if *authority != self.owner {
+            return Err(TokenError::NotOwner.into());
+        }
+        self.track_transfers = enabled;
+        Ok(())
+    }
+
+    // Set total_supply to the sum of all balances and return it. A burn takes
+    // from a balance and the supply alike, so every token in existence still
+    // sits in some balance.
+    pub fn recompute_supply(&mut self, authority: &Pubkey) -> Result<u64, ProgramError> {
+        if *authority != self.owner {
+            return Err(TokenError::NotOwner.into());
+        }
+
+        let total_supply = self
+            .balances
+            .iter()
+            .try_fold(0u64, |total, (_, balance)| total.checked_add(*balance))
+            .ok_or(TokenError::Overflow)?;
+        self.total_supply = total_supply;
+        Ok(total_supply)
+    }
+
+    // Cap the number of balance entries. Existing holders keep transacting when
+    // the cap is lowered below the current count, only new entries are refused.
+    pub fn set_max_holders(&mut self, authority: &Pubkey, max_holders: Option<u64>) -> ProgramResult {
+        if *authority != self.owner {
+            return Err(TokenError::NotOwner.into());
+        }
+        self.max_holders = max_holders;
+        Ok(())
+    }
+
+    // Turn the full freeze on or off on behalf of the token owner
+    pub fn set_full_freeze(&mut self, authority: &Pubkey, enabled: bool) -> ProgramResult {
+        if *authority != self.owner {
+            return Err(TokenError::NotOwner.into());
+        }
+        self.full_freeze = enabled;
+        Ok(())
+    }
+
+    // Pause or resume writes on behalf of the token owner
+    pub fn set_paused(&mut self, authority: &Pubkey, paused: bool) -> ProgramResult {
+        if *authority != self.owner {
+            return Err(TokenError::NotOwner.into());
+        }
+        self.paused = paused;
+        Ok(())
+    }
+
+    // Get the token balance of an account
+    pub fn get_balance(&self, account: &Pubkey) -> Option<u64> {
+        self.balances
+            .iter()
+            .find(|(acc, _)| *acc == *account)
+            .map(|(_, balance)| *balance)
+    }
+
+    // Get the token balances of several accounts, 0 for accounts without an entry
+    pub fn get_balances(&self, accounts: &[Pubkey]) -> Vec<u64> {
+        accounts
+            .iter()
+            .map(|account| self.get_balance(account).unwrap_or(0))
+            .collect()
+    }
+
+    // Get the count largest balances, largest first. Equal balances are ordered
+    // by pubkey bytes ascending so every call returns the same list.
+    pub fn top_holders(&self, count: usize) -> Vec<(Pubkey, u64)> {
+        let mut holders = self.balances.clone();
+        holders.sort_by(|(a_key, a_balance), (b_key, b_balance)| {
+            b_balance.cmp(a_balance).then_with(|| a_key.cmp(b_key))
+        });
+        holders.truncate(count);
+        holders
+    }
+
+    // Approve a spender to spend tokens on behalf of the sender
+    pub fn approve(
+        &mut self,
+        sender: &Pubkey,
+        spender: &Pubkey,
+        amount: u64,
+    ) -> Result<ApprovalEvent, ProgramError> {
+        *self.allowance_mut(sender, spender)? = amount;
+        Ok(self.approval_event(ApprovalKind::Set, sender, spender, amount))
+    }
+
+    // Raise an allowance by amount, starting from 0 for a new spender
+    pub fn increase_allowance(
+        &mut self,
+        sender: &Pubkey,
+        spender: &Pubkey,
+        amount: u64,
+    ) -> Result<ApprovalEvent, ProgramError> {
+        let allowance = self.allowance_mut(sender, spender)?;
+        *allowance = allowance.checked_add(amount).ok_or(TokenError::Overflow)?;
+        let allowance = *allowance;
+        Ok(self.approval_event(ApprovalKind::Increase, sender, spender, allowance))
+    }
+
+    // Lower an allowance by amount, which must not exceed it
+    pub fn decrease_allowance(
+        &mut self,
+        sender: &Pubkey,
+        spender: &Pubkey,
+        amount: u64,
+    ) -> Result<ApprovalEvent, ProgramError> {
+        let allowance = self
+            .allowances
+            .iter_mut()
+            .find(|(owner, spender_account, _)| *owner == *sender && *spender_account == *spender)
+            .map(|(_, _, allowance)| allowance)
+            .ok_or(TokenError::InsufficientAllowance)?;
+        *allowance = allowance
+            .checked_sub(amount)
+            .ok_or(TokenError::InsufficientAllowance)?;
+        let allowance = *allowance;
+        Ok(self.approval_event(ApprovalKind::Decrease, sender, spender, allowance))
+    }
+
+    // Zero an allowance, the entry is dropped by the next prune
+    pub fn revoke(&mut self, sender: &Pubkey, spender: &Pubkey) -> ApprovalEvent {
+        if let Some((_, _, allowance)) = self
+            .allowances
+            .iter_mut()
+            .find(|(owner, spender_account, _)| *owner == *sender && *spender_account == *spender)
+        {
+            *allowance = 0;
+        }
+        self.approval_event(ApprovalKind::Revoke, sender, spender, 0)
+    }
+
+    // The (sender, spender) allowance, created at 0 while a slot is free
+    fn allowance_mut(&mut self, sender: &Pubkey, spender: &Pubkey) -> Result<&mut u64, ProgramError> {
+        // Nobody holds the key of the default pubkey, an allowance for it is a mistake
+        if *spender == Pubkey::default() {
+            return Err(TokenError::InvalidSpender.into());
+        }
+
+        let allowance_index = self
+            .allowances
+            .iter()
+            .position(|(owner, spender_account, _)| *owner == *sender && *spender_account == *spender);
+
+        let index = match allowance_index {
+            Some(index) => index,
+            None => {
+                if self.allowances.len() >= MAX_ALLOWANCES {
+                    return Err(TokenError::AllowanceLimitReached.into());
+                }
+                self.allowances.push((*sender, *spender, 0));
+                self.allowances.len() - 1
+            }
+        };
+        Ok(&mut self.allowances[index].2)
+    }
+
+    fn approval_event(
+        &self,
+        kind: ApprovalKind,
+        owner: &Pubkey,
+        spender: &Pubkey,
+        allowance: u64,
+    ) -> ApprovalEvent {
+        ApprovalEvent {
+            kind,
+            owner: *owner,
+            spender: *spender,
+            allowance,
+        }
+    }
+
+    // Check whether an account is frozen
+    pub fn is_frozen(&self, account: &Pubkey) -> bool {
+        self.frozen.contains(account)
+    }
+
+    // Freeze an account on behalf of the token owner
+    pub fn freeze_account(&mut self, authority: &Pubkey, account: &Pubkey) -> ProgramResult {
+        if *authority != self.owner {
+            return Err(TokenError::NotOwner.into());
+        }
+        if !self.is_frozen(account) {
+            self.frozen.push(*account);
+        }
+        Ok(())
+    }
+
+    // Thaw a frozen account on behalf of the token owner
+    pub fn thaw_account(&mut self, authority: &Pubkey, account: &Pubkey) -> ProgramResult {
+        if *authority != self.owner {
+            return Err(TokenError::NotOwner.into());
+        }
+        self.frozen.retain(|frozen| frozen != account);
+        Ok(())
+    }
+
+    // Collect the balance, allowance and frozen flag an account panel needs
+    pub fn account_summary(&self, owner: &Pubkey, spender: &Pubkey) -> AccountSummary {
+        AccountSummary {
+            balance: self.get_balance(owner).unwrap_or(0),
+            allowance: self.get_allowance(owner, spender).unwrap_or(0),
+            frozen: self.is_frozen(owner),
+        }
+    }
+
+    // Remove zeroed allowances and return how many slots were freed
+    pub fn prune_allowances(&mut self) -> usize {
+        let before = self.allowances.len();
+        self.allowances.retain(|(_, _, amount)| *amount > 0);
+        before - self.allowances.len()
+    }
+
+    // Get the amount a spender may still spend on behalf of an owner
+    pub fn get_allowance(&self, owner: &Pubkey, spender: &Pubkey) -> Option<u64> {
+        self.allowances
+            .iter()
+            .find(|(acc, spender_account, _)| *acc == *owner && *spender_account == *spender)
+            .map(|(_, _, amount)| *amount)
+    }
+
+    // Count the nonzero allowances across all owners, revoked ones are excluded
+    pub fn active_allowance_count(&self) -> usize {
+        self.allowances
+            .iter()
+            .filter(|(_, _, amount)| *amount > 0)
+            .count()
+    }
+
+    // Transfer tokens from owner to recipient, spending the spender's allowance.
+    // The allowance and the balances are tracked separately, so a spender that
+    // is also the recipient is debited once from the allowance and credited
+    // once in the balances. Allowances granted by or to a frozen account can't
+    // be used until it is thawed.
+    pub fn transfer_from(
+        &mut self,
+        owner: &Pubkey,
+        spender: &Pubkey,
+        recipient: &Pubkey,
+        amount: u64,
+    ) -> ProgramResult {
+        if self.is_frozen(owner) || self.is_frozen(spender) || self.is_frozen(recipient) {
+            return Err(TokenError::AccountFrozen.into());
+        }
+
+        let allowance_index = self
+            .allowances
+            .iter()
+            .position(|(acc, spender_account, _)| *acc == *owner && *spender_account == *spender)
+            .ok_or(TokenError::InsufficientAllowance)?;
+
+        if self.allowances[allowance_index].2 < amount {
+            return Err(TokenError::InsufficientAllowance.into());
+        }
+
+        // The freeze checks are already done, so skip straight to the balances
+        // instead of going through transfer and scanning the frozen list again
+        self.move_balance(owner, recipient, amount)?;
+        self.allowances[allowance_index].2 -= amount;
+
+        Ok(())
+    }
+}
+
+// Get the account at position i, failing cleanly when too few were passed
+fn account_at<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    i: usize,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    accounts.get(i).ok_or_else(|| {
+        msg!("Expected an account at position {}, got {} accounts", i, accounts.len());
+        ProgramError::NotEnoughAccountKeys
+    })
+}
+
+// Process instructions, public so examples and off-chain harnesses can drive
+// the program without a validator
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    // Match the instruction data to call corresponding functions
+    let instruction = TokenInstruction::unpack(instruction_data)?;
+
+    // A full freeze stops everything, reads included, until the owner lifts it.
+    // An ordinary pause only stops writes. Only the header is read, state
+    // without a valid one is left to the instruction to reject.
+    if let Some(state) = accounts.first() {
+        let data = state.try_borrow_data()?;
+        if let Ok(flags) = Token::header_flags(&data) {
+            let is_switch = matches!(
+                instruction,
+                TokenInstruction::SetFullFreeze { .. } | TokenInstruction::SetPaused { .. }
+            );
+            if flags & FLAG_FULL_FREEZE != 0
+                && !matches!(instruction, TokenInstruction::SetFullFreeze { .. })
+            {
+                return Err(TokenError::FullyFrozen.into());
+            }
+            if flags & FLAG_PAUSED != 0 && !is_switch && !instruction.is_read_only() {
+                return Err(TokenError::Paused.into());
+            }
+        }
+    }
+
+    match instruction {
+        TokenInstruction::Initialize { total_supply } => {
+            // The state must hold the base layout plus the owner's balance entry
+            let state = account_at(accounts, 0)?;
+            if !state.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            // Anything carrying the magic was initialized before, whether or not
+            // it still unpacks, and re-running Initialize would wipe it
+            if Token::layout_version(&state.try_borrow_data()?).is_ok() {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            if state.data_len() < Token::BASE_LEN + Token::BALANCE_LEN {
+                msg!(
+                    "State account holds {} bytes, initialize needs {}",
+                    state.data_len(),
+                    Token::BASE_LEN + Token::BALANCE_LEN
+                );
+                return Err(ProgramError::AccountDataTooSmall);
+            }
+
+            let mut token = Token {
+                total_supply: 0,
+                owner: *state.key,
+                balances: vec![],
+                allowances: vec![],
+                frozen: vec![],
+                track_transfers: false,
+                transfer_counts: vec![],
+                max_holders: None,
+                full_freeze: false,
+                paused: false,
+            };
+            token.initialize(total_supply, *state.key);
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            Ok(())
+        }
+        TokenInstruction::Transfer { amount } => process_transfer(accounts, amount),
+        TokenInstruction::GetBalance { strict } => {
+            // Write the account's balance to return data as a little-endian u64
+            let state = account_at(accounts, 0)?;
+            let account = account_at(accounts, 1)?;
+
+            // Never report a 0 balance for an account that isn't a token. Only a
+            // missing magic or unknown layout means that, other unpack errors
+            // such as CorruptState are passed on as they are.
+            let data = state.try_borrow_data()?;
+            if !matches!(Token::layout_version(&data), Ok(version) if Token::is_known_layout(version)) {
+                return Err(ProgramError::UninitializedAccount);
+            }
+            let token = Token::unpack_from_slice(&data)?;
+            let balance = match token.get_balance(account.key) {
+                Some(balance) => balance,
+                None if strict => return Err(TokenError::RecipientNotFound.into()),
+                None => 0,
+            };
+            msg!("Account balance: {}", balance);
+            set_return_data(&balance.to_le_bytes());
+            Ok(())
+        }
+        TokenInstruction::Approve { spender, amount } => {
+            // Approve a spender to spend tokens on behalf of the signing owner
+            let state = account_at(accounts, 0)?;
+            let owner = account_at(accounts, 1)?;
+            if !owner.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            let mut token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+            let event = token.approve(owner.key, &spender, amount)?;
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            event.log();
+            Ok(())
+        }
+        TokenInstruction::TransferFrom { amount } => {
+            // Transfer tokens from owner to recipient on the spender's allowance
+            let state = account_at(accounts, 0)?;
+            let spender = account_at(accounts, 1)?;
+            let owner = account_at(accounts, 2)?;
+            let recipient = account_at(accounts, 3)?;
+
+            if !spender.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            let mut token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+            token.transfer_from(owner.key, spender.key, recipient.key, amount)?;
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            Ok(())
+        }
+        TokenInstruction::GetInitialized => {
+            // Write 1 to return data if the state account holds a token, 0 otherwise
+            let state = account_at(accounts, 0)?;
+            let initialized = Token::is_initialized(&state.try_borrow_data()?);
+            set_return_data(&[initialized as u8]);
+            Ok(())
+        }
+        TokenInstruction::GetActiveAllowanceCount => {
+            // Write the active allowance count to return data as a little-endian u64
+            let token = Token::unpack_from_slice(&account_at(accounts, 0)?.try_borrow_data()?)?;
+            let count = token.active_allowance_count() as u64;
+            set_return_data(&count.to_le_bytes());
+            Ok(())
+        }
+        TokenInstruction::GetBalances { accounts: queried } => {
+            // Write the balances to return data as a Borsh encoded Vec<u64>
+            let token = Token::unpack_from_slice(&account_at(accounts, 0)?.try_borrow_data()?)?;
+            let balances = token.get_balances(&queried);
+            set_return_data(&pack_u64_vec(&balances));
+            Ok(())
+        }
+        TokenInstruction::PruneAllowances => {
+            // Drop zeroed allowances and persist the smaller state
+            let state = account_at(accounts, 0)?;
+            let mut token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+            let pruned = token.prune_allowances();
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            msg!("Pruned {} allowances", pruned);
+            Ok(())
+        }
+        TokenInstruction::FreezeAccount { account } => {
+            // Freeze an account, the token owner must sign
+            let state = account_at(accounts, 0)?;
+            let authority = account_at(accounts, 1)?;
+            if !authority.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            let mut token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+            token.freeze_account(authority.key, &account)?;
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            Ok(())
+        }
+        TokenInstruction::ThawAccount { account } => {
+            // Thaw a frozen account, the token owner must sign
+            let state = account_at(accounts, 0)?;
+            let authority = account_at(accounts, 1)?;
+            if !authority.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            let mut token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+            token.thaw_account(authority.key, &account)?;
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            Ok(())
+        }
+        TokenInstruction::GetAccountSummary { owner, spender } => {
+            // Write the packed AccountSummary to return data
+            let token = Token::unpack_from_slice(&account_at(accounts, 0)?.try_borrow_data()?)?;
+            set_return_data(&token.account_summary(&owner, &spender).pack());
+            Ok(())
+        }
+        TokenInstruction::SetTransferTracking { enabled } => {
+            // Toggle transfer counting, the token owner must sign
+            let state = account_at(accounts, 0)?;
+            let authority = account_at(accounts, 1)?;
+            if !authority.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            let mut token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+            token.set_transfer_tracking(authority.key, enabled)?;
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            Ok(())
+        }
+        TokenInstruction::GetTransferCount { account } => {
+            // Write the transfer count to return data as a little-endian u64
+            let token = Token::unpack_from_slice(&account_at(accounts, 0)?.try_borrow_data()?)?;
+            set_return_data(&token.get_transfer_count(&account).to_le_bytes());
+            Ok(())
+        }
+        TokenInstruction::TransferWithDeadline {
+            amount,
+            deadline_slot,
+        } => {
+            // Reject transactions that sat around past their deadline
+            let slot = Clock::get()?.slot;
+            if slot > deadline_slot {
+                msg!("Deadline slot {} passed, current slot is {}", deadline_slot, slot);
+                return Err(TokenError::TransactionExpired.into());
+            }
+            process_transfer(accounts, amount)
+        }
+        TokenInstruction::RecomputeSupply => {
+            // One-shot repair of a drifted total_supply, the token owner must sign
+            let state = account_at(accounts, 0)?;
+            let authority = account_at(accounts, 1)?;
+            if !authority.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            let mut token = Token::unpack_unchecked(&state.try_borrow_data()?)?;
+            let previous_supply = token.total_supply;
+            let total_supply = token.recompute_supply(authority.key)?;
+            msg!("Recomputed total supply from {} to {}", previous_supply, total_supply);
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            Ok(())
+        }
+        TokenInstruction::GetTopHolders { count } => {
+            // Write the holders to return data as a Borsh encoded Vec<(Pubkey, u64)>
+            let token = Token::unpack_from_slice(&account_at(accounts, 0)?.try_borrow_data()?)?;
+            set_return_data(&pack_holders(&token.top_holders(count as usize)));
+            Ok(())
+        }
+        TokenInstruction::WithdrawExcess {
+            amount,
+            destination,
+        } => {
+            // Token balances are bookkeeping only, the lamports the state account
+            // owes are just its rent-exempt reserve. Anything above it was sent
+            // by mistake and may be recovered by the token owner.
+            let state = account_at(accounts, 0)?;
+            let authority = account_at(accounts, 1)?;
+            let destination_info = account_at(accounts, 2)?;
+            if !authority.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if state.owner != program_id {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            if *destination_info.key != destination {
+                return Err(ProgramError::InvalidArgument);
+            }
+            // Crediting the state with its own lamports would read its balance
+            // before the debit and leave it richer than before
+            if destination == *state.key {
+                return Err(ProgramError::InvalidArgument);
+            }
+
+            let token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+            if *authority.key != token.owner {
+                return Err(TokenError::NotOwner.into());
+            }
+
+            let reserve = Rent::get()?.minimum_balance(state.data_len());
+            let excess = state.lamports().saturating_sub(reserve);
+            if amount > excess {
+                msg!("Requested {} lamports, only {} are in excess", amount, excess);
+                return Err(ProgramError::InsufficientFunds);
+            }
+
+            let destination_lamports = destination_info
+                .lamports()
+                .checked_add(amount)
+                .ok_or(TokenError::Overflow)?;
+            **state.try_borrow_mut_lamports()? -= amount;
+            **destination_info.try_borrow_mut_lamports()? = destination_lamports;
+            Ok(())
+        }
+        TokenInstruction::SetMaxHolders { max_holders } => {
+            // Change the holder cap, the token owner must sign
+            let state = account_at(accounts, 0)?;
+            let authority = account_at(accounts, 1)?;
+            if !authority.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            let mut token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+            token.set_max_holders(authority.key, max_holders)?;
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            Ok(())
+        }
+        TokenInstruction::IncreaseAllowance { spender, amount }
+        | TokenInstruction::DecreaseAllowance { spender, amount } => {
+            // Adjust the signing owner's allowance relative to its current value
+            let state = account_at(accounts, 0)?;
+            let owner = account_at(accounts, 1)?;
+            if !owner.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            let mut token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+            let event = match instruction {
+                TokenInstruction::IncreaseAllowance { .. } => {
+                    token.increase_allowance(owner.key, &spender, amount)?
+                }
+                _ => token.decrease_allowance(owner.key, &spender, amount)?,
+            };
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            event.log();
+            Ok(())
+        }
+        TokenInstruction::Revoke { spender } => {
+            // Zero the signing owner's allowance
+            let state = account_at(accounts, 0)?;
+            let owner = account_at(accounts, 1)?;
+            if !owner.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            let mut token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+            let event = token.revoke(owner.key, &spender);
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            event.log();
+            Ok(())
+        }
+        TokenInstruction::Burn { amount } => {
+            // Burn from the signing holder's own balance
+            let state = account_at(accounts, 0)?;
+            let holder = account_at(accounts, 1)?;
+            if !holder.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            let mut token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+            token.burn(holder.key, amount)?;
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            msg!("Burned {}, total supply {}", amount, token.total_supply);
+            Ok(())
+        }
+        TokenInstruction::SetFullFreeze { enabled } => {
+            // Start or end a full freeze, the token owner must sign
+            let state = account_at(accounts, 0)?;
+            let authority = account_at(accounts, 1)?;
+            if !authority.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            let mut token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+            token.set_full_freeze(authority.key, enabled)?;
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            Ok(())
+        }
+        TokenInstruction::SetPaused { paused } => {
+            // Pause or resume writes, the token owner must sign
+            let state = account_at(accounts, 0)?;
+            let authority = account_at(accounts, 1)?;
+            if !authority.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            let mut token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+            token.set_paused(authority.key, paused)?;
+            token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+            Ok(())
+        }
+        TokenInstruction::GetAccountVersion => {
+            // Write the layout version byte to return data
+            let state = account_at(accounts, 0)?;
+            let version = Token::layout_version(&state.try_borrow_data()?)?;
+            set_return_data(&[version]);
+            Ok(())
+        }
+    }
+}
+
+// Transfer tokens from sender to recipient
+// Role accounts only supply a key, every balance lives in the state account's
+// data. Passing the state account again as sender or recipient is the owner's
+// own balance entry and can't leave a second copy of the data to overwrite.
+fn process_transfer(accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let state = account_at(accounts, 0)?;
+    let sender = account_at(accounts, 1)?;
+    let recipient = account_at(accounts, 2)?;
+    if !sender.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut token = Token::unpack_from_slice(&state.try_borrow_data()?)?;
+    token.transfer(sender.key, recipient.key, amount)?;
+    token.pack_into_slice(&mut state.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+// Borsh layout of a Vec<u64>: a little-endian u32 length, then each value little-endian
+fn pack_u64_vec(values: &[u64]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(4 + values.len() * 8);
+    data.extend_from_slice(&(values.len() as u32).to_le_bytes());
+    for value in values {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data
+}
+
+// Borsh layout of a Vec<(Pubkey, u64)>: a little-endian u32 length, then each
+// pubkey followed by its little-endian balance
+fn pack_holders(holders: &[(Pubkey, u64)]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(4 + holders.len() * Token::BALANCE_LEN);
+    data.extend_from_slice(&(holders.len() as u32).to_le_bytes());
+    for (holder, balance) in holders {
+        data.extend_from_slice(holder.as_ref());
+        data.extend_from_slice(&balance.to_le_bytes());
+    }
+    data
+}
+
+impl TokenInstruction {
+    // Queries that only read the state and write return data
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Self::GetBalance { .. }
+                | Self::GetInitialized
+                | Self::GetActiveAllowanceCount
+                | Self::GetBalances { .. }
+                | Self::GetAccountSummary { .. }
+                | Self::GetTransferCount { .. }
+                | Self::GetTopHolders { .. }
+                | Self::GetAccountVersion
+        )
+    }
+
+    // Pack the instruction into instruction data, the inverse of unpack
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Self::Initialize { total_supply } => {
+                buf.push(0);
+                buf.extend_from_slice(&total_supply.to_le_bytes());
+            }
+            Self::Transfer { amount } => {
+                buf.push(1);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::GetBalance { strict } => {
+                buf.push(2);
+                // Lenient queries keep the original one-byte encoding
+                if *strict {
+                    buf.push(1);
+                }
+            }
+            Self::Approve { spender, amount } => {
+                buf.push(3);
+                buf.extend_from_slice(spender.as_ref());
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::TransferFrom { amount } => {
+                buf.push(4);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::GetInitialized => buf.push(5),
+            Self::GetActiveAllowanceCount => buf.push(6),
+            Self::GetBalances { accounts } => {
+                buf.push(7);
+                buf.extend_from_slice(&(accounts.len() as u32).to_le_bytes());
+                for account in accounts {
+                    buf.extend_from_slice(account.as_ref());
+                }
+            }
+            Self::PruneAllowances => buf.push(8),
+            Self::FreezeAccount { account } => {
+                buf.push(9);
+                buf.extend_from_slice(account.as_ref());
+            }
+            Self::ThawAccount { account } => {
+                buf.push(10);
+                buf.extend_from_slice(account.as_ref());
+            }
+            Self::GetAccountSummary { owner, spender } => {
+                buf.push(11);
+                buf.extend_from_slice(owner.as_ref());
+                buf.extend_from_slice(spender.as_ref());
+            }
+            Self::SetTransferTracking { enabled } => {
+                buf.push(12);
+                buf.push(*enabled as u8);
+            }
+            Self::GetTransferCount { account } => {
+                buf.push(13);
+                buf.extend_from_slice(account.as_ref());
+            }
+            Self::TransferWithDeadline {
+                amount,
+                deadline_slot,
+            } => {
+                buf.push(14);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&deadline_slot.to_le_bytes());
+            }
+            Self::RecomputeSupply => buf.push(15),
+            Self::GetAccountVersion => buf.push(19),
+            Self::IncreaseAllowance { spender, amount } => {
+                buf.push(20);
+                buf.extend_from_slice(spender.as_ref());
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::DecreaseAllowance { spender, amount } => {
+                buf.push(21);
+                buf.extend_from_slice(spender.as_ref());
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::Revoke { spender } => {
+                buf.push(22);
+                buf.extend_from_slice(spender.as_ref());
+            }
+            Self::Burn { amount } => {
+                buf.push(23);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::SetFullFreeze { enabled } => {
+                buf.push(24);
+                buf.push(*enabled as u8);
+            }
+            Self::SetPaused { paused } => {
+                buf.push(25);
+                buf.push(*paused as u8);
+            }
+            Self::GetTopHolders { count } => {
+                buf.push(16);
+                buf.extend_from_slice(&count.to_le_bytes());
+            }
+            Self::WithdrawExcess {
+                amount,
+                destination,
+            } => {
+                buf.push(17);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(destination.as_ref());
+            }
+            Self::SetMaxHolders { max_holders } => {
+                buf.push(18);
+                match max_holders {
+                    Some(max_holders) => {
+                        buf.push(1);
+                        buf.extend_from_slice(&max_holders.to_le_bytes());
+                    }
+                    None => buf.push(0),
+                }
+            }
+        }
+        buf
+    }
+
+    // Unpack the instruction data
+    fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        use ProgramError::InvalidInstructionData as InvalidInstruction;
+        let (&tag, rest) = data.split_first().ok_or(InvalidInstruction)?;
+        Ok(match tag {
+            0 => Self::Initialize {
+                total_supply: Self::unpack_u64(rest)?,
+            },
+            1 => Self::Transfer {
+                amount: Self::unpack_u64(rest)?,
+            },
+            2 => Self::GetBalance {
+                strict: matches!(rest.first(), Some(&strict) if strict != 0),
+            },
+            3 => {
+                let (spender, amount) = Self::unpack_approve(rest)?;
+                Self::Approve { spender, amount }
+            }
+            4 => Self::TransferFrom {
+                amount: Self::unpack_u64(rest)?,
+            },
+            5 => Self::GetInitialized,
+            6 => Self::GetActiveAllowanceCount,
+            7 => Self::GetBalances {
+                accounts: Self::unpack_pubkeys(rest)?,
+            },
+            8 => Self::PruneAllowances,
+            9 => Self::FreezeAccount {
+                account: Self::unpack_pubkey(rest)?.0,
+            },
+            10 => Self::ThawAccount {
+                account: Self::unpack_pubkey(rest)?.0,
+            },
+            11 => {
+                let (owner, rest) = Self::unpack_pubkey(rest)?;
+                let (spender, _rest) = Self::unpack_pubkey(rest)?;
+                Self::GetAccountSummary { owner, spender }
+            }
+            12 => {
+                let (&enabled, _rest) = rest.split_first().ok_or(InvalidInstruction)?;
+                Self::SetTransferTracking {
+                    enabled: enabled != 0,
+                }
+            }
+            13 => Self::GetTransferCount {
+                account: Self::unpack_pubkey(rest)?.0,
+            },
+            14 => {
+                let (amount, deadline_slot) = Self::unpack_deadline(rest)?;
+                Self::TransferWithDeadline {
+                    amount,
+                    deadline_slot,
+                }
+            }
+            15 => Self::RecomputeSupply,
+            16 => {
+                let count = rest
+                    .get(..4)
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .map(u32::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                if count as usize > MAX_TOP_HOLDERS_QUERY {
+                    return Err(TokenError::BatchTooLarge.into());
+                }
+                Self::GetTopHolders { count }
+            }
+            17 => {
+                let (amount, destination) = Self::unpack_withdraw(rest)?;
+                Self::WithdrawExcess {
+                    amount,
+                    destination,
+                }
+            }
+            18 => {
+                let (&has_max_holders, rest) = rest.split_first().ok_or(InvalidInstruction)?;
+                Self::SetMaxHolders {
+                    max_holders: match has_max_holders {
+                        0 => None,
+                        _ => Some(Self::unpack_u64(rest)?),
+                    },
+                }
+            }
+            19 => Self::GetAccountVersion,
+            20 => {
+                let (spender, amount) = Self::unpack_approve(rest)?;
+                Self::IncreaseAllowance { spender, amount }
+            }
+            21 => {
+                let (spender, amount) = Self::unpack_approve(rest)?;
+                Self::DecreaseAllowance { spender, amount }
+            }
+            22 => Self::Revoke {
+                spender: Self::unpack_pubkey(rest)?.0,
+            },
+            23 => Self::Burn {
+                amount: Self::unpack_u64(rest)?,
+            },
+            24 => {
+                let (&enabled, _rest) = rest.split_first().ok_or(InvalidInstruction)?;
+                Self::SetFullFreeze {
+                    enabled: enabled != 0,
+                }
+            }
+            25 => {
+                let (&paused, _rest) = rest.split_first().ok_or(InvalidInstruction)?;
+                Self::SetPaused {
+                    paused: paused != 0,
+                }
+            }
+            _ => return Err(InvalidInstruction),
+        })
+    }
+
+    fn unpack_u64(input: &[u8]) -> Result<u64, ProgramError> {
+        if input.len() < 8 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (bytes, _rest) = input.split_at(8);
+        Ok(u64::from_le_bytes(
+            bytes.try_into().expect("slice with incorrect length"),
+        ))
+    }
+
+    fn unpack_approve(input: &[u8]) -> Result<(Pubkey, u64), ProgramError> {
+        let (spender, rest) = Self::unpack_pubkey(input)?;
+        let amount = Self::unpack_u64(rest)?;
+        Ok((spender, amount))
+    }
+
+    fn unpack_withdraw(input: &[u8]) -> Result<(u64, Pubkey), ProgramError> {
+        if input.len() < 8 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (amount, rest) = input.split_at(8);
+        let (destination, _rest) = Self::unpack_pubkey(rest)?;
+        Ok((Self::unpack_u64(amount)?, destination))
+    }
+
+    fn unpack_deadline(input: &[u8]) -> Result<(u64, u64), ProgramError> {
+        if input.len() < 16 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (amount, deadline_slot) = input.split_at(8);
+        Ok((Self::unpack_u64(amount)?, Self::unpack_u64(deadline_slot)?))
+    }
+
+    // A little-endian u32 count followed by that many pubkeys
+    fn unpack_pubkeys(input: &[u8]) -> Result<Vec<Pubkey>, ProgramError> {
+        if input.len() < 4 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (count, mut rest) = input.split_at(4);
+        let count = u32::from_le_bytes(count.try_into().expect("slice with incorrect length")) as usize;
+
+        if count > MAX_BALANCES_QUERY {
+            return Err(TokenError::BatchTooLarge.into());
+        }
+        if rest.len() < count * 32 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        let mut keys = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (key, next) = Self::unpack_pubkey(rest)?;
+            keys.push(key);
+            rest = next;
+        }
+        Ok(keys)
+    }
+
+    fn unpack_pubkey(input: &[u8]) -> Result<(Pubkey, &[u8]), ProgramError> {
+        if input.len() < 32 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (key, rest) = input.split_at(32);
+        Ok((Pubkey::new_from_array(key.try_into().expect("slice with incorrect length")), rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::{entrypoint::SUCCESS, program_stubs};
+    use std::{cell::Cell, sync::Once};
+
+    thread_local! {
+        static CLOCK_SLOT: Cell<u64> = const { Cell::new(0) };
+    }
+
+    // Syscall stubs serving a per-thread mocked clock and the default rent
+    struct TestSyscallStubs;
+
+    impl program_stubs::SyscallStubs for TestSyscallStubs {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock {
+                slot: CLOCK_SLOT.with(|slot| slot.get()),
+                ..Clock::default()
+            };
+            unsafe { *(var_addr as *mut Clock) = clock };
+            SUCCESS
+        }
+
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Rent) = Rent::default() };
+            SUCCESS
+        }
+    }
+
+    fn install_syscall_stubs() {
+        static STUBS: Once = Once::new();
+        STUBS.call_once(|| {
+            program_stubs::set_syscall_stubs(Box::new(TestSyscallStubs));
+        });
+    }
+
+    fn set_clock_slot(slot: u64) {
+        install_syscall_stubs();
+        CLOCK_SLOT.with(|current| current.set(slot));
+    }
+
+    fn new_token(owner: &Pubkey, total_supply: u64) -> Token {
+        let mut token = Token {
+            total_supply: 0,
+            owner: *owner,
+            balances: vec![],
+            allowances: vec![],
+            frozen: vec![],
+            track_transfers: false,
+            transfer_counts: vec![],
+            max_holders: None,
+            full_freeze: false,
+            paused: false,
+        };
+        token.initialize(total_supply, *owner);
+        token
+    }
+
+    fn account_info<'a>(
+        key: &'a Pubkey,
+        is_signer: bool,
+        lamports: &'a mut u64,
+        data: &'a mut [u8],
+        owner: &'a Pubkey,
+    ) -> AccountInfo<'a> {
+        AccountInfo::new(key, is_signer, true, lamports, data, owner, false, 0)
+    }
+
+    #[test]
+    fn packed_token_is_initialized() {
+        let owner = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.approve(&owner, &Pubkey::new_unique(), 10).unwrap();
+
+        let mut data = vec![0; 256];
+        token.pack_into_slice(&mut data).unwrap();
+
+        assert!(Token::is_initialized(&data));
+        let unpacked = Token::unpack_from_slice(&data).unwrap();
+        assert_eq!(unpacked.owner, owner);
+        assert_eq!(unpacked.total_supply, 1_000);
+        assert_eq!(unpacked.balances, token.balances);
+        assert_eq!(unpacked.allowances, token.allowances);
+    }
+
+    #[test]
+    fn packed_token_reports_the_current_layout_version() {
+        let owner = Pubkey::new_unique();
+        let mut data = vec![0; 256];
+        new_token(&owner, 1_000).pack_into_slice(&mut data).unwrap();
+
+        assert_eq!(Token::layout_version(&data), Ok(TOKEN_LAYOUT_VERSION));
+        assert_eq!(data[TOKEN_MAGIC.len()], TOKEN_LAYOUT_VERSION);
+        assert_eq!(
+            Token::layout_version(&[0; 8]),
+            Err(ProgramError::UninitializedAccount)
+        );
+
+        // A layout this program doesn't know is refused rather than misread
+        data[TOKEN_MAGIC.len()] = TOKEN_LAYOUT_VERSION + 1;
+        assert_eq!(Token::layout_version(&data), Ok(TOKEN_LAYOUT_VERSION + 1));
+        assert!(matches!(
+            Token::unpack_from_slice(&data),
+            Err(ProgramError::InvalidAccountData)
+        ));
+    }
+
+    #[test]
+    fn unpack_rejects_lengths_the_data_cannot_hold() {
+        let token = new_token(&Pubkey::new_unique(), 1_000);
+        let mut data = vec![0; token.packed_len()];
+        token.pack_into_slice(&mut data).unwrap();
+
+        // The balances length, then the allowances length after the one balance
+        let balances_at = FLAGS_OFFSET + 1 + 8 + 32;
+        let allowances_at = balances_at + 4 + Token::BALANCE_LEN;
+        for (at, len) in [(balances_at, u32::MAX), (balances_at, 2), (allowances_at, u32::MAX)] {
+            let mut corrupt = data.clone();
+            corrupt[at..at + 4].copy_from_slice(&len.to_le_bytes());
+            assert!(
+                matches!(Token::unpack_unchecked(&corrupt), Err(ProgramError::InvalidAccountData)),
+                "length {} at {}",
+                len,
+                at
+            );
+        }
+    }
+
+    #[test]
+    fn unpack_detects_supply_not_matching_balances() {
+        let owner = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+
+        // Two balances of 600 and 300 under a supply of 1_000
+        let mut data = TOKEN_MAGIC.to_vec();
+        data.push(TOKEN_LAYOUT_VERSION);
+        data.push(0);
+        data.extend_from_slice(&1_000u64.to_le_bytes());
+        data.extend_from_slice(owner.as_ref());
+        data.extend_from_slice(&2u32.to_le_bytes());
+        for (account, balance) in [(owner, 600u64), (holder, 300)] {
+            data.extend_from_slice(account.as_ref());
+            data.extend_from_slice(&balance.to_le_bytes());
+        }
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.push(0);
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.push(0);
+        data.extend_from_slice(&0u64.to_le_bytes());
+
+        if cfg!(debug_assertions) {
+            assert!(matches!(
+                Token::unpack_from_slice(&data),
+                Err(e) if e == TokenError::CorruptState.into()
+            ));
+        }
+        let token = Token::unpack_unchecked(&data).unwrap();
+        assert_eq!(token.total_supply, 1_000);
+        assert_eq!(token.get_balance(&holder), Some(300));
+    }
+
+    #[test]
+    fn zeroed_account_is_not_initialized() {
+        assert!(!Token::is_initialized(&[0; 256]));
+        assert!(!Token::is_initialized(&[]));
+    }
+
+    #[test]
+    fn approve_requires_a_signing_owner_and_a_real_spender() {
+        let program_id = Pubkey::new_unique();
+        let state_key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let spender = Pubkey::new_unique();
+        let mut state_lamports = 0;
+        let mut owner_lamports = 0;
+        let mut state_data = vec![0; 512];
+        let mut owner_data = vec![];
+        new_token(&owner, 1_000).pack_into_slice(&mut state_data).unwrap();
+
+        let approve = |spender| TokenInstruction::Approve { spender, amount: 10 }.pack();
+        {
+            let accounts = [
+                account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id),
+                account_info(&owner, false, &mut owner_lamports, &mut owner_data, &program_id),
+            ];
+            assert_eq!(
+                process_instruction(&program_id, &accounts, &approve(spender)),
+                Err(ProgramError::MissingRequiredSignature)
+            );
+        }
+
+        let accounts = [
+            account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id),
+            account_info(&owner, true, &mut owner_lamports, &mut owner_data, &program_id),
+        ];
+        assert_eq!(
+            process_instruction(&program_id, &accounts, &approve(Pubkey::default())),
+            Err(TokenError::InvalidSpender.into())
+        );
+        process_instruction(&program_id, &accounts, &approve(spender)).unwrap();
+
+        let token = Token::unpack_from_slice(&accounts[0].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(token.get_allowance(&owner, &spender), Some(10));
+        assert_eq!(token.get_allowance(&owner, &Pubkey::default()), None);
+    }
+
+    #[test]
+    fn owner_burns_part_then_all_of_their_balance() {
+        let owner = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.transfer(&owner, &holder, 400).unwrap();
+
+        token.burn(&owner, 250).unwrap();
+        assert_eq!(token.get_balance(&owner), Some(350));
+        assert_eq!(token.total_supply, 750);
+
+        assert_eq!(token.burn(&owner, 351), Err(ProgramError::InsufficientFunds));
+        token.burn(&owner, 350).unwrap();
+        assert_eq!(token.get_balance(&owner), None);
+        assert_eq!(token.total_supply, 400);
+        assert_eq!(token.burn(&owner, 1), Err(ProgramError::InvalidArgument));
+
+        // The supply still adds up, so the state unpacks after a round trip
+        let mut data = vec![0; 256];
+        token.pack_into_slice(&mut data).unwrap();
+        let token = Token::unpack_from_slice(&data).unwrap();
+        assert_eq!(token.balances, vec![(holder, 400)]);
+    }
+
+    #[test]
+    fn transfer_to_the_state_account_credits_the_owner_entry() {
+        let program_id = Pubkey::new_unique();
+        let state_key = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let mut state_lamports = 0;
+        let mut holder_lamports = 0;
+        let mut state_data = vec![0; 512];
+        let mut holder_data = vec![];
+        // Initialize makes the state account the owner of the whole supply
+        let mut token = new_token(&state_key, 1_000);
+        token.transfer(&state_key, &holder, 300).unwrap();
+        token.pack_into_slice(&mut state_data).unwrap();
+
+        let state = account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id);
+        let holder = account_info(&holder, true, &mut holder_lamports, &mut holder_data, &program_id);
+        let accounts = [state.clone(), holder.clone(), state.clone()];
+        let instruction = TokenInstruction::Transfer { amount: 100 }.pack();
+        process_instruction(&program_id, &accounts, &instruction).unwrap();
+
+        let token = Token::unpack_from_slice(&state.try_borrow_data().unwrap()).unwrap();
+        assert_eq!(token.get_balance(&state_key), Some(800));
+        assert_eq!(token.get_balance(holder.key), Some(200));
+        assert_eq!(token.total_supply, 1_000);
+    }
+
+    #[test]
+    fn full_freeze_rejects_reads_until_the_owner_lifts_it() {
+        let program_id = Pubkey::new_unique();
+        let state_key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let mut state_lamports = 0;
+        let mut owner_lamports = 0;
+        let mut holder_lamports = 0;
+        let mut state_data = vec![0; 512];
+        let mut owner_data = vec![];
+        let mut holder_data = vec![];
+        new_token(&owner, 1_000).pack_into_slice(&mut state_data).unwrap();
+
+        let state = account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id);
+        let owner = account_info(&owner, true, &mut owner_lamports, &mut owner_data, &program_id);
+        let holder = account_info(&holder, true, &mut holder_lamports, &mut holder_data, &program_id);
+        let get_balance = TokenInstruction::GetBalance { strict: false }.pack();
+        let freeze = |enabled| TokenInstruction::SetFullFreeze { enabled }.pack();
+        let pause = |paused| TokenInstruction::SetPaused { paused }.pack();
+        let transfer = TokenInstruction::Transfer { amount: 1 }.pack();
+        let transferring = [state.clone(), owner.clone(), holder.clone()];
+
+        // Reads work as long as no full freeze is on
+        let reader = [state.clone(), owner.clone()];
+        process_instruction(&program_id, &reader, &get_balance).unwrap();
+
+        // An ordinary pause stops writes only
+        process_instruction(&program_id, &reader, &pause(true)).unwrap();
+        process_instruction(&program_id, &reader, &get_balance).unwrap();
+        process_instruction(&program_id, &reader, &TokenInstruction::GetAccountVersion.pack()).unwrap();
+        assert_eq!(
+            process_instruction(&program_id, &transferring, &transfer),
+            Err(TokenError::Paused.into())
+        );
+        process_instruction(&program_id, &reader, &pause(false)).unwrap();
+
+        assert_eq!(
+            process_instruction(&program_id, &[state.clone(), holder.clone()], &freeze(true)),
+            Err(TokenError::NotOwner.into())
+        );
+        process_instruction(&program_id, &reader, &freeze(true)).unwrap();
+
+        for (accounts, instruction) in [
+            (&reader[..], &get_balance),
+            (&reader[..], &TokenInstruction::GetAccountVersion.pack()),
+            (&transferring[..], &transfer),
+        ] {
+            assert_eq!(
+                process_instruction(&program_id, accounts, instruction),
+                Err(TokenError::FullyFrozen.into())
+            );
+        }
+
+        process_instruction(&program_id, &reader, &freeze(false)).unwrap();
+        process_instruction(&program_id, &reader, &get_balance).unwrap();
+        process_instruction(&program_id, &transferring, &transfer).unwrap();
+    }
+
+    #[test]
+    fn freeze_gate_reads_only_the_header() {
+        let program_id = Pubkey::new_unique();
+        let state_key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.full_freeze = true;
+        let mut state_data = vec![0; 256];
+        token.pack_into_slice(&mut state_data).unwrap();
+        assert_eq!(state_data[FLAGS_OFFSET], FLAG_FULL_FREEZE);
+        assert_eq!(Token::header_flags(&state_data), Ok(FLAG_FULL_FREEZE));
+
+        // A balances length the data cannot hold, the body no longer unpacks
+        let balances_at = FLAGS_OFFSET + 1 + 8 + 32;
+        state_data[balances_at..balances_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Token::unpack_unchecked(&state_data).is_err());
+
+        let (mut state_lamports, mut owner_lamports) = (0, 0);
+        let mut owner_data = vec![];
+        let accounts = [
+            account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id),
+            account_info(&owner, false, &mut owner_lamports, &mut owner_data, &program_id),
+        ];
+        let instruction = TokenInstruction::GetBalance { strict: false }.pack();
+        assert_eq!(
+            process_instruction(&program_id, &accounts, &instruction),
+            Err(TokenError::FullyFrozen.into())
+        );
+    }
+
+    #[test]
+    fn version_1_state_unpacks_with_the_flags_off() {
+        let owner = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.full_freeze = true;
+        token.paused = true;
+        let mut data = vec![0; token.packed_len()];
+        token.pack_into_slice(&mut data).unwrap();
+
+        // A version 1 account is the same layout without the flags byte
+        let mut v1 = data.clone();
+        v1.remove(FLAGS_OFFSET);
+        v1[TOKEN_MAGIC.len()] = 1;
+        let token = Token::unpack_from_slice(&v1).unwrap();
+        assert!(!token.full_freeze);
+        assert!(!token.paused);
+        assert_eq!(token.get_balance(&owner), Some(1_000));
+
+        // Writing it back stores the current layout
+        token.pack_into_slice(&mut data).unwrap();
+        assert_eq!(Token::layout_version(&data), Ok(TOKEN_LAYOUT_VERSION));
+        assert!(!Token::unpack_from_slice(&data).unwrap().full_freeze);
+    }
+
+    #[test]
+    fn get_balance_rejects_uninitialized_state() {
+        let program_id = Pubkey::new_unique();
+        let state_key = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let mut state_lamports = 0;
+        let mut holder_lamports = 0;
+        let mut holder_data = vec![];
+
+        for mut state_data in [vec![0; 256], vec![]] {
+            let accounts = [
+                account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id),
+                account_info(&holder, false, &mut holder_lamports, &mut holder_data, &program_id),
+            ];
+            for strict in [false, true] {
+                let instruction = TokenInstruction::GetBalance { strict }.pack();
+                assert_eq!(
+                    process_instruction(&program_id, &accounts, &instruction),
+                    Err(ProgramError::UninitializedAccount)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn get_balance_passes_corrupt_state_through() {
+        let program_id = Pubkey::new_unique();
+        let state_key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut state_lamports = 0;
+        let mut owner_lamports = 0;
+        let mut state_data = vec![0; 256];
+        let mut owner_data = vec![];
+        new_token(&owner, 1_000).pack_into_slice(&mut state_data).unwrap();
+        // total_supply follows the magic, the version and the flags byte
+        let supply_offset = FLAGS_OFFSET + 1;
+        state_data[supply_offset..supply_offset + 8].copy_from_slice(&999u64.to_le_bytes());
+
+        let accounts = [
+            account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id),
+            account_info(&owner, false, &mut owner_lamports, &mut owner_data, &program_id),
+        ];
+        let instruction = TokenInstruction::GetBalance { strict: false }.pack();
+        let result = process_instruction(&program_id, &accounts, &instruction);
+        if cfg!(debug_assertions) {
+            assert_eq!(result, Err(TokenError::CorruptState.into()));
+        } else {
+            assert_eq!(result, Ok(()));
+        }
+    }
+
+    #[test]
+    fn active_allowance_count_skips_revoked() {
+        let owner = Pubkey::new_unique();
+        let other_owner = Pubkey::new_unique();
+        let spender = Pubkey::new_unique();
+        let revoked = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+
+        token.approve(&owner, &spender, 10).unwrap();
+        token.approve(&owner, &revoked, 5).unwrap();
+        token.approve(&other_owner, &spender, 1).unwrap();
+        token.approve(&owner, &revoked, 0).unwrap();
+
+        assert_eq!(token.active_allowance_count(), 2);
+    }
+
+    #[test]
+    fn get_balances_mixes_existing_and_missing_accounts() {
+        let owner = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let missing = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.balances.push((holder, 25));
+
+        let mut data = vec![7];
+        data.extend_from_slice(&3u32.to_le_bytes());
+        for key in [owner, missing, holder] {
+            data.extend_from_slice(key.as_ref());
+        }
+        let queried = match TokenInstruction::unpack(&data).unwrap() {
+            TokenInstruction::GetBalances { accounts } => accounts,
+            other => panic!("unexpected instruction {:?}", other),
+        };
+
+        let balances = token.get_balances(&queried);
+        assert_eq!(balances, vec![1_000, 0, 25]);
+
+        let encoded = pack_u64_vec(&balances);
+        assert_eq!(&encoded[..4], &3u32.to_le_bytes());
+        assert_eq!(&encoded[4..12], &1_000u64.to_le_bytes());
+        assert_eq!(&encoded[12..20], &0u64.to_le_bytes());
+        assert_eq!(&encoded[20..], &25u64.to_le_bytes());
+    }
+
+    #[test]
+    fn get_balances_rejects_oversized_batch() {
+        let mut data = vec![7];
+        data.extend_from_slice(&(MAX_BALANCES_QUERY as u32 + 1).to_le_bytes());
+        data.resize(data.len() + (MAX_BALANCES_QUERY + 1) * 32, 0);
+
+        assert_eq!(
+            TokenInstruction::unpack(&data).unwrap_err(),
+            TokenError::BatchTooLarge.into()
+        );
+    }
+
+    #[test]
+    fn instruction_wire_format_is_little_endian() {
+        assert_eq!(ENDIANNESS, "little");
+
+        let transfer = TokenInstruction::Transfer { amount: 1 };
+        let data = transfer.pack();
+        assert_eq!(data, vec![1, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(TokenInstruction::unpack(&data).unwrap(), transfer);
+
+        let initialize = TokenInstruction::Initialize {
+            total_supply: 0x0102_0304_0506_0708,
+        };
+        assert_eq!(
+            initialize.pack(),
+            vec![0, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]
+        );
+    }
+
+    #[test]
+    fn every_instruction_round_trips() {
+        let spender = Pubkey::new_unique();
+        let instructions = vec![
+            TokenInstruction::Initialize { total_supply: 1_000 },
+            TokenInstruction::Transfer { amount: u64::MAX },
+            TokenInstruction::GetBalance { strict: false },
+            TokenInstruction::GetBalance { strict: true },
+            TokenInstruction::Approve { spender, amount: 42 },
+            TokenInstruction::TransferFrom { amount: 7 },
+            TokenInstruction::GetInitialized,
+            TokenInstruction::GetActiveAllowanceCount,
+            TokenInstruction::GetBalances {
+                accounts: vec![spender, Pubkey::new_unique()],
+            },
+            TokenInstruction::PruneAllowances,
+            TokenInstruction::FreezeAccount { account: spender },
+            TokenInstruction::ThawAccount { account: spender },
+            TokenInstruction::GetAccountSummary {
+                owner: Pubkey::new_unique(),
+                spender,
+            },
+            TokenInstruction::SetTransferTracking { enabled: true },
+            TokenInstruction::GetTransferCount { account: spender },
+            TokenInstruction::TransferWithDeadline {
+                amount: 5,
+                deadline_slot: 99,
+            },
+            TokenInstruction::RecomputeSupply,
+            TokenInstruction::GetTopHolders { count: 3 },
+            TokenInstruction::WithdrawExcess {
+                amount: 5_000,
+                destination: spender,
+            },
+            TokenInstruction::SetMaxHolders {
+                max_holders: Some(3),
+            },
+            TokenInstruction::SetMaxHolders { max_holders: None },
+            TokenInstruction::GetAccountVersion,
+            TokenInstruction::IncreaseAllowance { spender, amount: 3 },
+            TokenInstruction::DecreaseAllowance { spender, amount: 2 },
+            TokenInstruction::Revoke { spender },
+            TokenInstruction::Burn { amount: 5 },
+            TokenInstruction::SetFullFreeze { enabled: true },
+            TokenInstruction::SetFullFreeze { enabled: false },
+            TokenInstruction::SetPaused { paused: true },
+            TokenInstruction::SetPaused { paused: false },
+        ];
+
+        for instruction in instructions {
+            assert_eq!(TokenInstruction::unpack(&instruction.pack()).unwrap(), instruction);
+        }
+    }
+
+    #[test]
+    fn approval_events_report_kind_and_resulting_allowance() {
+        let owner = Pubkey::new_unique();
+        let spender = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        let event = |kind, allowance| ApprovalEvent {
+            kind,
+            owner,
+            spender,
+            allowance,
+        };
+
+        assert_eq!(
+            token.approve(&owner, &spender, 50),
+            Ok(event(ApprovalKind::Set, 50))
+        );
+        assert_eq!(
+            token.increase_allowance(&owner, &spender, 25),
+            Ok(event(ApprovalKind::Increase, 75))
+        );
+        assert_eq!(
+            token.decrease_allowance(&owner, &spender, 70),
+            Ok(event(ApprovalKind::Decrease, 5))
+        );
+        assert_eq!(
+            token.decrease_allowance(&owner, &spender, 6),
+            Err(TokenError::InsufficientAllowance.into())
+        );
+        assert_eq!(token.revoke(&owner, &spender), event(ApprovalKind::Revoke, 0));
+        assert_eq!(token.get_allowance(&owner, &spender), Some(0));
+
+        // Increasing from nothing creates the allowance, overflowing it is refused
+        let other = Pubkey::new_unique();
+        token.increase_allowance(&owner, &other, u64::MAX).unwrap();
+        assert_eq!(
+            token.increase_allowance(&owner, &other, 1),
+            Err(TokenError::Overflow.into())
+        );
+        assert_eq!(token.get_allowance(&owner, &other), Some(u64::MAX));
+    }
+
+    #[test]
+    fn allowance_cap_is_freed_by_pruning() {
+        let owner = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        let spenders: Vec<Pubkey> = (0..MAX_ALLOWANCES).map(|_| Pubkey::new_unique()).collect();
+        for spender in &spenders {
+            token.approve(&owner, spender, 1).unwrap();
+        }
+
+        let late_spender = Pubkey::new_unique();
+        assert_eq!(
+            token.approve(&owner, &late_spender, 1),
+            Err(TokenError::AllowanceLimitReached.into())
+        );
+
+        // Updating an existing allowance never needs a new slot
+        token.approve(&owner, &spenders[0], 0).unwrap();
+        assert_eq!(token.prune_allowances(), 1);
+        assert_eq!(token.allowances.len(), MAX_ALLOWANCES - 1);
+
+        token.approve(&owner, &late_spender, 1).unwrap();
+        assert_eq!(token.get_allowance(&owner, &late_spender), Some(1));
+    }
+
+    #[test]
+    fn account_summary_reports_balance_allowance_and_frozen() {
+        let owner = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let spender = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.balances.push((holder, 40));
+        token.approve(&holder, &spender, 15).unwrap();
+        token.freeze_account(&owner, &holder).unwrap();
+
+        let summary = token.account_summary(&holder, &spender);
+        assert_eq!(
+            summary,
+            AccountSummary {
+                balance: 40,
+                allowance: 15,
+                frozen: true,
+            }
+        );
+
+        let mut expected = [0; AccountSummary::LEN];
+        expected[..8].copy_from_slice(&40u64.to_le_bytes());
+        expected[8..16].copy_from_slice(&15u64.to_le_bytes());
+        expected[16] = 1;
+        assert_eq!(summary.pack(), expected);
+    }
+
+    #[test]
+    fn frozen_accounts_cannot_transfer() {
+        let owner = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.balances.push((holder, 0));
+
+        assert_eq!(
+            token.freeze_account(&holder, &holder),
+            Err(TokenError::NotOwner.into())
+        );
+        token.freeze_account(&owner, &holder).unwrap();
+        assert_eq!(
+            token.transfer(&owner, &holder, 1),
+            Err(TokenError::AccountFrozen.into())
+        );
+
+        token.thaw_account(&owner, &holder).unwrap();
+        token.transfer(&owner, &holder, 1).unwrap();
+        assert_eq!(token.get_balance(&holder), Some(1));
+    }
+
+    #[test]
+    fn transfer_counts_track_send_and_receive() {
+        let owner = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.balances.push((holder, 0));
+
+        // Counting is off by default
+        token.transfer(&owner, &holder, 10).unwrap();
+        assert_eq!(token.get_transfer_count(&holder), 0);
+        assert!(token.transfer_counts.is_empty());
+
+        token.set_transfer_tracking(&owner, true).unwrap();
+        token.transfer(&owner, &holder, 10).unwrap();
+        token.transfer(&holder, &owner, 5).unwrap();
+
+        assert_eq!(token.get_transfer_count(&owner), 2);
+        assert_eq!(token.get_transfer_count(&holder), 2);
+    }
+
+    #[test]
+    fn transfer_with_one_account_is_rejected() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![0; 512];
+        let state = account_info(&key, true, &mut lamports, &mut data, &program_id);
+
+        let instruction = TokenInstruction::Transfer { amount: 1 }.pack();
+        assert_eq!(
+            process_instruction(&program_id, &[state], &instruction),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+    }
+
+    #[test]
+    fn every_instruction_rejects_missing_accounts() {
+        set_clock_slot(0);
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut token_data = vec![0; 512];
+        new_token(&owner, 1_000).pack_into_slice(&mut token_data).unwrap();
+
+        // Each instruction with the number of accounts it reads
+        let instructions = [
+            (TokenInstruction::Initialize { total_supply: 1 }, 1),
+            (TokenInstruction::Transfer { amount: 1 }, 3),
+            (TokenInstruction::GetBalance { strict: true }, 2),
+            (TokenInstruction::Approve { spender: key, amount: 1 }, 2),
+            (TokenInstruction::TransferFrom { amount: 1 }, 4),
+            (TokenInstruction::GetInitialized, 1),
+            (TokenInstruction::GetActiveAllowanceCount, 1),
+            (TokenInstruction::GetBalances { accounts: vec![key] }, 1),
+            (TokenInstruction::PruneAllowances, 1),
+            (TokenInstruction::FreezeAccount { account: key }, 2),
+            (TokenInstruction::ThawAccount { account: key }, 2),
+            (TokenInstruction::GetAccountSummary { owner, spender: key }, 1),
+            (TokenInstruction::SetTransferTracking { enabled: true }, 2),
+            (TokenInstruction::GetTransferCount { account: key }, 1),
+            (
+                TokenInstruction::TransferWithDeadline {
+                    amount: 1,
+                    deadline_slot: 10,
+                },
+                3,
+            ),
+            (TokenInstruction::RecomputeSupply, 2),
+            (TokenInstruction::GetTopHolders { count: 1 }, 1),
+            (
+                TokenInstruction::WithdrawExcess {
+                    amount: 1,
+                    destination: key,
+                },
+                3,
+            ),
+            (TokenInstruction::SetMaxHolders { max_holders: None }, 2),
+            (TokenInstruction::GetAccountVersion, 1),
+            (TokenInstruction::IncreaseAllowance { spender: key, amount: 1 }, 2),
+            (TokenInstruction::DecreaseAllowance { spender: key, amount: 1 }, 2),
+            (TokenInstruction::Revoke { spender: key }, 2),
+            (TokenInstruction::Burn { amount: 1 }, 2),
+            (TokenInstruction::SetFullFreeze { enabled: true }, 2),
+            (TokenInstruction::SetPaused { paused: true }, 2),
+        ];
+
+        for (instruction, required) in instructions {
+            // Pass the state, the signing owner and filler accounts, one short of
+            // what the arm reads
+            let mut data = token_data.clone();
+            let mut lamports = [0; 4];
+            let [state_lamports, owner_lamports, filler_lamports, last_lamports] = &mut lamports;
+            let (mut owner_data, mut filler_data, mut last_data) = (vec![], vec![], vec![]);
+            let mut accounts = vec![
+                account_info(&key, false, state_lamports, &mut data, &program_id),
+                account_info(&owner, true, owner_lamports, &mut owner_data, &program_id),
+                account_info(&key, false, filler_lamports, &mut filler_data, &program_id),
+                account_info(&key, false, last_lamports, &mut last_data, &program_id),
+            ];
+            accounts.truncate(required - 1);
+
+            assert_eq!(
+                process_instruction(&program_id, &accounts, &instruction.pack()),
+                Err(ProgramError::NotEnoughAccountKeys),
+                "{:?} with {} accounts",
+                instruction,
+                accounts.len()
+            );
+        }
+    }
+
+    #[test]
+    fn transfer_moves_persisted_balances() {
+        let program_id = Pubkey::new_unique();
+        let state_key = Pubkey::new_unique();
+        let recipient_key = Pubkey::new_unique();
+        let mut token = new_token(&state_key, 100);
+        token.balances.push((recipient_key, 0));
+
+        let mut state_data = vec![0; 512];
+        token.pack_into_slice(&mut state_data).unwrap();
+        let (mut state_lamports, mut sender_lamports, mut recipient_lamports) = (0, 0, 0);
+        let mut sender_data = vec![];
+        let mut recipient_data = vec![];
+        let accounts = [
+            account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id),
+            account_info(&state_key, true, &mut sender_lamports, &mut sender_data, &program_id),
+            account_info(&recipient_key, false, &mut recipient_lamports, &mut recipient_data, &program_id),
+        ];
+
+        let instruction = TokenInstruction::Transfer { amount: 30 }.pack();
+        process_instruction(&program_id, &accounts, &instruction).unwrap();
+
+        let token = Token::unpack_from_slice(&accounts[0].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(token.get_balance(&state_key), Some(70));
+        assert_eq!(token.get_balance(&recipient_key), Some(30));
+    }
+
+    #[test]
+    fn recompute_supply_repairs_drifted_total() {
+        let program_id = Pubkey::new_unique();
+        let state_key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.balances.push((Pubkey::new_unique(), 250));
+        token.total_supply = 7;
+
+        let mut state_data = vec![0; 512];
+        token.pack_into_slice(&mut state_data).unwrap();
+        let (mut state_lamports, mut owner_lamports) = (0, 0);
+        let mut owner_data = vec![];
+        let accounts = [
+            account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id),
+            account_info(&owner, true, &mut owner_lamports, &mut owner_data, &program_id),
+        ];
+
+        let instruction = TokenInstruction::RecomputeSupply.pack();
+        process_instruction(&program_id, &accounts, &instruction).unwrap();
+
+        let token = Token::unpack_from_slice(&accounts[0].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(token.total_supply, 1_250);
+    }
+
+    #[test]
+    fn recompute_supply_is_owner_only_and_checked() {
+        let owner = Pubkey::new_unique();
+        let mut token = new_token(&owner, u64::MAX);
+        token.total_supply = 0;
+
+        assert_eq!(
+            token.recompute_supply(&Pubkey::new_unique()),
+            Err(TokenError::NotOwner.into())
+        );
+
+        token.balances.push((Pubkey::new_unique(), 1));
+        assert_eq!(
+            token.recompute_supply(&owner),
+            Err(TokenError::Overflow.into())
+        );
+        assert_eq!(token.total_supply, 0);
+    }
+
+    #[test]
+    fn top_holders_break_ties_by_pubkey() {
+        let owner = Pubkey::new_unique();
+        let mut token = new_token(&owner, 500);
+        let mut tied = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        for holder in [tied[2], tied[0], tied[1]] {
+            token.balances.push((holder, 100));
+        }
+        token.balances.push((Pubkey::new_unique(), 20));
+        tied.sort();
+
+        let holders = token.top_holders(4);
+        assert_eq!(holders[0], (owner, 500));
+        assert_eq!(
+            holders[1..].iter().map(|(holder, _)| *holder).collect::<Vec<_>>(),
+            tied.to_vec()
+        );
+        assert!(holders[1..].iter().all(|(_, balance)| *balance == 100));
+
+        // The order doesn't depend on the order of the balance entries
+        token.balances.swap(1, 3);
+        assert_eq!(token.top_holders(4), holders);
+        assert_eq!(token.top_holders(2), holders[..2].to_vec());
+    }
+
+    #[test]
+    fn top_holders_query_is_bounded() {
+        let mut data = vec![16];
+        data.extend_from_slice(&(MAX_TOP_HOLDERS_QUERY as u32 + 1).to_le_bytes());
+        assert_eq!(
+            TokenInstruction::unpack(&data).unwrap_err(),
+            TokenError::BatchTooLarge.into()
+        );
+
+        // The largest response still fits the return data
+        let count = MAX_TOP_HOLDERS_QUERY as u32;
+        assert_eq!(
+            TokenInstruction::unpack(&TokenInstruction::GetTopHolders { count }.pack()).unwrap(),
+            TokenInstruction::GetTopHolders { count }
+        );
+        let holders = vec![(Pubkey::new_unique(), u64::MAX); MAX_TOP_HOLDERS_QUERY];
+        assert!(pack_holders(&holders).len() <= MAX_RETURN_DATA);
+        assert!(pack_holders(&[holders.clone(), holders[..1].to_vec()].concat()).len() > MAX_RETURN_DATA);
+    }
+
+    #[test]
+    fn withdraw_excess_recovers_only_lamports_above_rent() {
+        install_syscall_stubs();
+        let program_id = Pubkey::new_unique();
+        let state_key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+        let token = new_token(&owner, 1_000);
+
+        let mut state_data = vec![0; 512];
+        token.pack_into_slice(&mut state_data).unwrap();
+        let reserve = Rent::default().minimum_balance(state_data.len());
+        let (mut state_lamports, mut owner_lamports, mut destination_lamports) =
+            (reserve + 5_000, 0, 10);
+        let (mut owner_data, mut destination_data) = (vec![], vec![]);
+        let accounts = [
+            account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id),
+            account_info(&owner, true, &mut owner_lamports, &mut owner_data, &program_id),
+            account_info(&destination, false, &mut destination_lamports, &mut destination_data, &program_id),
+        ];
+
+        let withdraw = |amount| {
+            let instruction = TokenInstruction::WithdrawExcess {
+                amount,
+                destination,
+            }
+            .pack();
+            process_instruction(&program_id, &accounts, &instruction)
+        };
+
+        assert_eq!(withdraw(5_001), Err(ProgramError::InsufficientFunds));
+        withdraw(5_000).unwrap();
+        assert_eq!(accounts[0].lamports(), reserve);
+        assert_eq!(accounts[2].lamports(), 5_010);
+        assert_eq!(withdraw(1), Err(ProgramError::InsufficientFunds));
+
+        // The token state itself is untouched
+        let unpacked = Token::unpack_from_slice(&accounts[0].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(unpacked.get_balance(&owner), Some(1_000));
+    }
+
+    #[test]
+    fn withdraw_excess_rejects_the_state_as_destination() {
+        install_syscall_stubs();
+        let program_id = Pubkey::new_unique();
+        let state_key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let token = new_token(&owner, 1_000);
+
+        let mut state_data = vec![0; 512];
+        token.pack_into_slice(&mut state_data).unwrap();
+        let reserve = Rent::default().minimum_balance(state_data.len());
+        let (mut state_lamports, mut owner_lamports) = (reserve + 5_000, 0);
+        let mut owner_data = vec![];
+        let state = account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id);
+        let accounts = [
+            state.clone(),
+            account_info(&owner, true, &mut owner_lamports, &mut owner_data, &program_id),
+            state,
+        ];
+
+        let instruction = TokenInstruction::WithdrawExcess {
+            amount: 5_000,
+            destination: state_key,
+        }
+        .pack();
+        assert_eq!(
+            process_instruction(&program_id, &accounts, &instruction),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(accounts[0].lamports(), reserve + 5_000);
+    }
+
+    #[test]
+    fn holder_cap_blocks_only_new_holders() {
+        let owner = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let newcomer = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.set_max_holders(&owner, Some(2)).unwrap();
+
+        token.transfer(&owner, &holder, 100).unwrap();
+        assert_eq!(
+            token.transfer(&owner, &newcomer, 100),
+            Err(TokenError::HolderLimitReached.into())
+        );
+        assert_eq!(token.get_balance(&newcomer), None);
+        assert_eq!(token.get_balance(&owner), Some(900));
+
+        // Existing holders keep transacting at the cap, also below a lowered cap
+        token.transfer(&holder, &owner, 40).unwrap();
+        token.set_max_holders(&owner, Some(1)).unwrap();
+        token.transfer(&owner, &holder, 10).unwrap();
+        assert_eq!(token.get_balance(&holder), Some(70));
+
+        token.set_max_holders(&owner, None).unwrap();
+        token.transfer(&holder, &newcomer, 5).unwrap();
+        assert_eq!(token.get_balance(&newcomer), Some(5));
+
+        assert_eq!(
+            token.set_max_holders(&holder, Some(10)),
+            Err(TokenError::NotOwner.into())
+        );
+    }
+
+    #[test]
+    fn holder_cap_survives_packing() {
+        let owner = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.set_max_holders(&owner, Some(5)).unwrap();
+
+        let mut data = vec![0; token.packed_len()];
+        token.pack_into_slice(&mut data).unwrap();
+        assert_eq!(Token::unpack_from_slice(&data).unwrap().max_holders, Some(5));
+
+        token.set_max_holders(&owner, None).unwrap();
+        token.pack_into_slice(&mut data).unwrap();
+        assert_eq!(Token::unpack_from_slice(&data).unwrap().max_holders, None);
+    }
+
+    fn process_get_balance(instruction_data: &[u8], queried: &Pubkey) -> ProgramResult {
+        let program_id = Pubkey::new_unique();
+        let state_key = Pubkey::new_unique();
+        let token = new_token(&state_key, 100);
+
+        let mut state_data = vec![0; 512];
+        token.pack_into_slice(&mut state_data).unwrap();
+        let (mut state_lamports, mut queried_lamports) = (0, 0);
+        let mut queried_data = vec![];
+        let accounts = [
+            account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id),
+            account_info(queried, false, &mut queried_lamports, &mut queried_data, &program_id),
+        ];
+        process_instruction(&program_id, &accounts, instruction_data)
+    }
+
+    #[test]
+    fn get_balance_of_unknown_account_defaults_to_zero() {
+        // The bare tag without a flag byte stays lenient
+        assert_eq!(
+            TokenInstruction::unpack(&[2]).unwrap(),
+            TokenInstruction::GetBalance { strict: false }
+        );
+        assert_eq!(process_get_balance(&[2], &Pubkey::new_unique()), Ok(()));
+    }
+
+    #[test]
+    fn strict_get_balance_rejects_unknown_account() {
+        let instruction = TokenInstruction::GetBalance { strict: true }.pack();
+        assert_eq!(
+            process_get_balance(&instruction, &Pubkey::new_unique()),
+            Err(TokenError::RecipientNotFound.into())
+        );
+    }
+
+    fn process_deadline_transfer(current_slot: u64, deadline_slot: u64) -> (ProgramResult, Token) {
+        let program_id = Pubkey::new_unique();
+        let state_key = Pubkey::new_unique();
+        let recipient_key = Pubkey::new_unique();
+        let mut token = new_token(&state_key, 100);
+        token.balances.push((recipient_key, 0));
+
+        let mut state_data = vec![0; 512];
+        token.pack_into_slice(&mut state_data).unwrap();
+        let (mut state_lamports, mut sender_lamports, mut recipient_lamports) = (0, 0, 0);
+        let mut sender_data = vec![];
+        let mut recipient_data = vec![];
+        let accounts = [
+            account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id),
+            account_info(&state_key, true, &mut sender_lamports, &mut sender_data, &program_id),
+            account_info(&recipient_key, false, &mut recipient_lamports, &mut recipient_data, &program_id),
+        ];
+
+        set_clock_slot(current_slot);
+        let instruction = TokenInstruction::TransferWithDeadline {
+            amount: 10,
+            deadline_slot,
+        }
+        .pack();
+        let result = process_instruction(&program_id, &accounts, &instruction);
+        let token = Token::unpack_from_slice(&accounts[0].try_borrow_data().unwrap()).unwrap();
+        (result, token)
+    }
+
+    #[test]
+    fn transfer_before_deadline_succeeds() {
+        let (result, token) = process_deadline_transfer(50, 50);
+        assert_eq!(result, Ok(()));
+        assert_eq!(token.get_balance(&token.owner), Some(90));
+    }
+
+    #[test]
+    fn transfer_requires_the_sender_to_sign() {
+        set_clock_slot(0);
+        let program_id = Pubkey::new_unique();
+        let state_key = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let thief = Pubkey::new_unique();
+        let mut token = new_token(&state_key, 100);
+        token.transfer(&state_key, &holder, 40).unwrap();
+
+        let mut state_data = vec![0; 512];
+        token.pack_into_slice(&mut state_data).unwrap();
+        let (mut state_lamports, mut holder_lamports, mut thief_lamports) = (0, 0, 0);
+        let mut holder_data = vec![];
+        let mut thief_data = vec![];
+        let accounts = [
+            account_info(&state_key, false, &mut state_lamports, &mut state_data, &program_id),
+            account_info(&holder, false, &mut holder_lamports, &mut holder_data, &program_id),
+            account_info(&thief, true, &mut thief_lamports, &mut thief_data, &program_id),
+        ];
+
+        for instruction in [
+            TokenInstruction::Transfer { amount: 40 },
+            TokenInstruction::TransferWithDeadline {
+                amount: 40,
+                deadline_slot: u64::MAX,
+            },
+        ] {
+            assert_eq!(
+                process_instruction(&program_id, &accounts, &instruction.pack()),
+                Err(ProgramError::MissingRequiredSignature)
+            );
+        }
+
+        let token = Token::unpack_from_slice(&accounts[0].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(token.get_balance(&holder), Some(40));
+        assert_eq!(token.get_balance(&thief), None);
+    }
+
+    #[test]
+    fn transfer_after_deadline_is_expired() {
+        let (result, token) = process_deadline_transfer(51, 50);
+        assert_eq!(result, Err(TokenError::TransactionExpired.into()));
+        assert_eq!(token.get_balance(&token.owner), Some(100));
+    }
+
+    #[test]
+    fn initialize_rejects_undersized_account() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![0; Token::BASE_LEN + Token::BALANCE_LEN - 1];
+        let state = account_info(&key, true, &mut lamports, &mut data, &program_id);
+
+        let instruction = TokenInstruction::Initialize { total_supply: 100 }.pack();
+        assert_eq!(
+            process_instruction(&program_id, std::slice::from_ref(&state), &instruction),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+        assert!(state.try_borrow_data().unwrap().iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn initialize_fits_exact_size() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![0; Token::BASE_LEN + Token::BALANCE_LEN];
+        let state = account_info(&key, true, &mut lamports, &mut data, &program_id);
+
+        let instruction = TokenInstruction::Initialize { total_supply: 100 }.pack();
+        process_instruction(&program_id, std::slice::from_ref(&state), &instruction).unwrap();
+
+        let token = Token::unpack_from_slice(&state.try_borrow_data().unwrap()).unwrap();
+        assert_eq!(token.packed_len(), Token::BASE_LEN + Token::BALANCE_LEN);
+        assert_eq!(token.get_balance(&key), Some(100));
+    }
+
+    #[test]
+    fn initialize_runs_once_and_only_for_the_signing_state_account() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![0; 512];
+        let initialize = TokenInstruction::Initialize { total_supply: 100 }.pack();
+
+        {
+            let state = account_info(&key, false, &mut lamports, &mut data, &program_id);
+            assert_eq!(
+                process_instruction(&program_id, std::slice::from_ref(&state), &initialize),
+                Err(ProgramError::MissingRequiredSignature)
+            );
+        }
+
+        let state = account_info(&key, true, &mut lamports, &mut data, &program_id);
+        process_instruction(&program_id, std::slice::from_ref(&state), &initialize).unwrap();
+        let mut token = Token::unpack_from_slice(&state.try_borrow_data().unwrap()).unwrap();
+        token.transfer(&key, &holder, 30).unwrap();
+        token.pack_into_slice(&mut state.try_borrow_mut_data().unwrap()).unwrap();
+
+        // A second Initialize leaves the live balances alone
+        assert_eq!(
+            process_instruction(&program_id, std::slice::from_ref(&state), &initialize),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+        let token = Token::unpack_from_slice(&state.try_borrow_data().unwrap()).unwrap();
+        assert_eq!(token.get_balance(&key), Some(70));
+        assert_eq!(token.get_balance(&holder), Some(30));
+    }
+
+    #[test]
+    fn frozen_owner_allowances_cannot_be_drained() {
+        let owner = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let spender = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.balances.push((holder, 100));
+        token.balances.push((spender, 0));
+        token.approve(&holder, &spender, 50).unwrap();
+        token.transfer_from(&holder, &spender, &spender, 10).unwrap();
+
+        token.freeze_account(&owner, &holder).unwrap();
+        assert_eq!(
+            token.transfer_from(&holder, &spender, &spender, 10),
+            Err(TokenError::AccountFrozen.into())
+        );
+        assert_eq!(token.get_allowance(&holder, &spender), Some(40));
+        assert_eq!(token.get_balance(&holder), Some(90));
+
+        // A frozen spender can't use allowances granted to it either
+        token.thaw_account(&owner, &holder).unwrap();
+        token.freeze_account(&owner, &spender).unwrap();
+        assert_eq!(
+            token.transfer_from(&holder, &spender, &owner, 10),
+            Err(TokenError::AccountFrozen.into())
+        );
+    }
+
+    #[test]
+    fn transfer_from_to_spender_credits_and_debits_once() {
+        let owner = Pubkey::new_unique();
+        let spender = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.balances.push((spender, 50));
+        token.approve(&owner, &spender, 300).unwrap();
+
+        token.transfer_from(&owner, &spender, &spender, 120).unwrap();
+
+        assert_eq!(token.get_balance(&spender), Some(170));
+        assert_eq!(token.get_balance(&owner), Some(880));
+        assert_eq!(token.get_allowance(&owner, &spender), Some(180));
+    }
+
+    #[test]
+    fn transfer_from_rejects_amount_above_allowance() {
+        let owner = Pubkey::new_unique();
+        let spender = Pubkey::new_unique();
+        let mut token = new_token(&owner, 1_000);
+        token.balances.push((spender, 0));
+        token.approve(&owner, &spender, 100).unwrap();
+
+        assert_eq!(
+            token.transfer_from(&owner, &spender, &spender, 101),
+            Err(TokenError::InsufficientAllowance.into())
+        );
+        assert_eq!(token.get_balance(&spender), Some(0));
+        assert_eq!(token.get_allowance(&owner, &spender), Some(100));
+    }
+
+    #[test]
+    fn transfer_from_matches_transfer_and_allowance_debit() {
+        let owner = Pubkey::new_unique();
+        let spender = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let setup = || {
+            let mut token = new_token(&owner, 1_000);
+            token.balances.push((spender, 5));
+            token.balances.push((recipient, u64::MAX - 10));
+            token.approve(&owner, &spender, 200).unwrap();
+            token.track_transfers = true;
+            token
+        };
+        let packed = |token: &Token| {
+            let mut data = vec![0u8; token.packed_len()];
+            token.pack_into_slice(&mut data).unwrap();
+            data
+        };
+
+        // Success, insufficient funds on the owner, and recipient overflow
+        // must leave the same state as the two-step path
+        for (from, to, amount) in [
+            (owner, spender, 150),
+            (spender, recipient, 6),
+            (owner, recipient, 11),
+        ] {
+            let mut single_pass = setup();
+            let mut two_step = setup();
+            if from == spender {
+                single_pass.approve(&spender, &owner, 200).unwrap();
+                two_step.approve(&spender, &owner, 200).unwrap();
+            }
+            let delegate = if from == owner { spender } else { owner };
+
+            let result = single_pass.transfer_from(&from, &delegate, &to, amount);
+            let expected = two_step.transfer(&from, &to, amount).map(|()| {
+                let allowance = two_step
+                    .allowances
+                    .iter_mut()
+                    .find(|(acc, spender_account, _)| *acc == from && *spender_account == delegate)
+                    .unwrap();
+                allowance.2 -= amount;
+            });
+
+            assert_eq!(result, expected);
+            assert_eq!(packed(&single_pass), packed(&two_step));
+        }
+    }
+
+    // Boundary inputs near 0 and u64::MAX for every mutating method. Each case
+    // must either succeed exactly or fail with the specific error, never wrap.
+    mod safe_math {
+        use super::*;
+
+        #[test]
+        fn transfer_zero_amount_is_a_no_op() {
+            let owner = Pubkey::new_unique();
+            let recipient = Pubkey::new_unique();
+            let mut token = new_token(&owner, 10);
+            token.balances.push((recipient, 0));
+
+            token.transfer(&owner, &recipient, 0).unwrap();
+
+            assert_eq!(token.get_balance(&owner), Some(10));
+            assert_eq!(token.get_balance(&recipient), Some(0));
+        }
+
+        #[test]
+        fn transfer_entire_max_balance() {
+            let owner = Pubkey::new_unique();
+            let recipient = Pubkey::new_unique();
+            let mut token = new_token(&owner, u64::MAX);
+            token.balances.push((recipient, 0));
+
+            token.transfer(&owner, &recipient, u64::MAX).unwrap();
+
+            assert_eq!(token.get_balance(&owner), Some(0));
+            assert_eq!(token.get_balance(&recipient), Some(u64::MAX));
+        }
+
+        #[test]
+        fn transfer_one_above_balance_is_insufficient() {
+            let owner = Pubkey::new_unique();
+            let recipient = Pubkey::new_unique();
+            let mut token = new_token(&owner, 0);
+            token.balances.push((recipient, 0));
+
+            assert_eq!(
+                token.transfer(&owner, &recipient, 1),
+                Err(ProgramError::InsufficientFunds)
+            );
+        }
+
+        #[test]
+        fn transfer_credit_past_max_overflows() {
+            let owner = Pubkey::new_unique();
+            let recipient = Pubkey::new_unique();
+            let mut token = new_token(&owner, 1);
+            token.balances.push((recipient, u64::MAX));
+
+            assert_eq!(
+                token.transfer(&owner, &recipient, 1),
+                Err(TokenError::Overflow.into())
+            );
+            assert_eq!(token.get_balance(&owner), Some(1));
+            assert_eq!(token.get_balance(&recipient), Some(u64::MAX));
+        }
+
+        #[test]
+        fn approve_zero_and_max() {
+            let owner = Pubkey::new_unique();
+            let spender = Pubkey::new_unique();
+            let mut token = new_token(&owner, 10);
+
+            token.approve(&owner, &spender, u64::MAX).unwrap();
+            assert_eq!(token.get_allowance(&owner, &spender), Some(u64::MAX));
+
+            token.approve(&owner, &spender, 0).unwrap();
+            assert_eq!(token.get_allowance(&owner, &spender), Some(0));
+        }
+
+        #[test]
+        fn transfer_from_spends_max_allowance_exactly() {
+            let owner = Pubkey::new_unique();
+            let spender = Pubkey::new_unique();
+            let mut token = new_token(&owner, u64::MAX);
+            token.balances.push((spender, 0));
+            token.approve(&owner, &spender, u64::MAX).unwrap();
+
+            token.transfer_from(&owner, &spender, &spender, u64::MAX).unwrap();
+
+            assert_eq!(token.get_balance(&spender), Some(u64::MAX));
+            assert_eq!(token.get_allowance(&owner, &spender), Some(0));
+        }
+
+        #[test]
+        fn transfer_from_on_zero_allowance_is_rejected() {
+            let owner = Pubkey::new_unique();
+            let spender = Pubkey::new_unique();
+            let mut token = new_token(&owner, 10);
+            token.balances.push((spender, 0));
+            token.approve(&owner, &spender, 0).unwrap();
+
+            assert_eq!(
+                token.transfer_from(&owner, &spender, &spender, 1),
+                Err(TokenError::InsufficientAllowance.into())
+            );
+        }
+
+        #[test]
+        fn transfer_from_overflow_keeps_allowance() {
+            let owner = Pubkey::new_unique();
+            let spender = Pubkey::new_unique();
+            let mut token = new_token(&owner, 1);
+            token.balances.push((spender, u64::MAX));
+            token.approve(&owner, &spender, 1).unwrap();
+
+            assert_eq!(
+                token.transfer_from(&owner, &spender, &spender, 1),
+                Err(TokenError::Overflow.into())
+            );
+            assert_eq!(token.get_allowance(&owner, &spender), Some(1));
+        }
+    }
+}
@@ -5,6 +5,13 @@ import {Solquad} from "../target/idl/solquad";
 
 import { utf8 } from "@coral-xyz/anchor/dist/cjs/utils/bytes";
 import {BN} from "bn.js";
+import { assert } from "chai";
+
+// Rounds that are never distributed never close
+const OPEN_END_SLOT = new BN("18446744073709551615");
+
+// Slots a distributed round stays open for, enough for its test's contributions to land
+const ROUND_SLOTS = 100;
 
 describe("solquad", async () => {
   const connection = new anchor.web3.Connection(anchor.web3.clusterApiUrl("devnet"), 'confirmed');
@@ -12,12 +19,22 @@ describe("solquad", async () => {
 
   const admin = anchor.web3.Keypair.generate();
   const admin2 = anchor.web3.Keypair.generate();
+  const admin3 = anchor.web3.Keypair.generate();
+  const admin4 = anchor.web3.Keypair.generate();
+  const admin5 = anchor.web3.Keypair.generate();
+  const admin6 = anchor.web3.Keypair.generate();
   const wallet = new anchor.Wallet(admin);
 
   const provider = new anchor.AnchorProvider(connection, wallet, {});
   const provider2 = new anchor.AnchorProvider(connection, new anchor.Wallet(admin2), {});
   const program = new Program<Solquad>(idl as Solquad, programId, provider)
   const program2 = new Program<Solquad>(idl as Solquad, programId, provider2)
+  const provider3 = new anchor.AnchorProvider(connection, new anchor.Wallet(admin3), {});
+  const program3 = new Program<Solquad>(idl as Solquad, programId, provider3)
+  const provider4 = new anchor.AnchorProvider(connection, new anchor.Wallet(admin4), {});
+  const program4 = new Program<Solquad>(idl as Solquad, programId, provider4)
+  const program5 = new Program<Solquad>(idl as Solquad, programId, new anchor.AnchorProvider(connection, new anchor.Wallet(admin5), {}))
+  const program6 = new Program<Solquad>(idl as Solquad, programId, new anchor.AnchorProvider(connection, new anchor.Wallet(admin6), {}))
 
   const escrowOwner = anchor.web3.Keypair.generate();
   const projectOwner1 = anchor.web3.Keypair.generate();
@@ -33,6 +50,7 @@ describe("solquad", async () => {
   const [escrowPDA] = await anchor.web3.PublicKey.findProgramAddressSync([
     utf8.encode("escrow"),
     admin.publicKey.toBuffer(),
+    roundSeed(0),
   ],
     program.programId
   );
@@ -40,6 +58,7 @@ describe("solquad", async () => {
   const [poolPDA] = anchor.web3.PublicKey.findProgramAddressSync([
     utf8.encode("pool"),
     admin.publicKey.toBuffer(),
+    roundSeed(0),
   ],
     program.programId
   );
@@ -55,27 +74,59 @@ describe("solquad", async () => {
   const [differentEscrowPDA] = anchor.web3.PublicKey.findProgramAddressSync([
     utf8.encode("escrow"),
     admin2.publicKey.toBuffer(),
+    roundSeed(0),
   ],
     program.programId
   );
 
   const [differentPoolPDA] = anchor.web3.PublicKey.findProgramAddressSync([
     utf8.encode("pool"),
-    admin2.publicKey.toBuffer()
+    admin2.publicKey.toBuffer(),
+    roundSeed(0),
+  ],
+    program.programId
+  );
+
+  const [emptyRoundEscrowPDA] = anchor.web3.PublicKey.findProgramAddressSync([
+    utf8.encode("escrow"),
+    admin3.publicKey.toBuffer(),
+    roundSeed(0),
+  ],
+    program.programId
+  );
+
+  const [emptyRoundPoolPDA] = anchor.web3.PublicKey.findProgramAddressSync([
+    utf8.encode("pool"),
+    admin3.publicKey.toBuffer(),
+    roundSeed(0),
+  ],
+    program.programId
+  );
+
+  const [emptyRoundProjectPDA] = anchor.web3.PublicKey.findProgramAddressSync([
+    utf8.encode("project"),
+    emptyRoundPoolPDA.toBytes(),
+    admin3.publicKey.toBuffer(),
   ],
     program.programId
   );
 
   airdrop(admin, provider);
   airdrop(admin2, provider);
+  airdrop(admin3, provider);
+  airdrop(admin4, provider);
+  airdrop(projectOwner1, provider);
+  airdrop(admin5, provider);
+  airdrop(projectOwner2, provider);
+  airdrop(admin6, provider);
 
   // Test 1
   it("initializes escrow and pool", async () => {
-    const poolIx = await program.methods.initializePool().accounts({
+    const poolIx = await program.methods.initializePool(new BN(0), await roundEnd(connection, ROUND_SLOTS)).accounts({
       poolAccount: poolPDA,
     }).instruction();
 
-    const escrowAndPoolTx = await program.methods.initializeEscrow(new BN(10000)).accounts({
+    const escrowAndPoolTx = await program.methods.initializeEscrow(new BN(0), new BN(10000)).accounts({
       escrowAccount: escrowPDA,
     })
     .postInstructions([poolIx])
@@ -103,55 +154,994 @@ describe("solquad", async () => {
 
     console.log("Project successfully created and added to the pool twice", addProjectTx);
 
+    // the second add is a no-op
     const data = await program.account.pool.fetch(poolPDA)
-    console.log("data projects", data.projects);
+    assert.equal(data.projects.length, 1);
+    assert.equal(data.totalProjects, 1);
+    const escrow = await program.account.escrow.fetch(escrowPDA)
+    assert.equal(escrow.projectRecieverAddresses.length, 1);
   })
 
   // Test 3
   it("tries to add the project in the different pool", async() => {
-    const poolIx = await program2.methods.initializePool().accounts({
+    const poolIx = await program2.methods.initializePool(new BN(0), OPEN_END_SLOT).accounts({
       poolAccount: differentPoolPDA,
     }).instruction();
 
-    const escrowIx = await program2.methods.initializeEscrow(new BN(10000)).accounts({
+    const escrowAndPoolTx = await program2.methods.initializeEscrow(new BN(0), new BN(10000)).accounts({
       escrowAccount: differentEscrowPDA,
     })
-    .instruction()
+    .postInstructions([poolIx])
+    .rpc();
 
-    const addProjectTx = await program2.methods.addProjectToPool().accounts({
+    console.log("Different pool is created", escrowAndPoolTx);
+
+    // the project lives at the address derived for the first pool
+    try {
+      await program2.methods.addProjectToPool().accounts({
+        projectAccount: projectPDA1,
+        poolAccount: differentPoolPDA,
+        escrowAccount: differentEscrowPDA
+      })
+      .rpc();
+      assert.fail("a project of another pool should be rejected");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "ProjectNotInPool");
+    }
+
+    const data = await program.account.pool.fetch(differentPoolPDA)
+    assert.equal(data.projects.length, 0);
+  });
+
+  // Test 4
+  it("votes for the project", async() => {
+    const voteTx = await program.methods.voteForProject(new BN(10)).accounts({
+      poolAccount: poolPDA,
       projectAccount: projectPDA1,
-      poolAccount: differentPoolPDA,
-      escrowAccount: differentEscrowPDA
     })
-    .preInstructions([escrowIx, poolIx])
     .rpc();
+    
+    console.log("Successfully voted on the project", voteTx);
+  });
 
-    console.log("Different pool is created and the project is inserted into it", addProjectTx);
+  // Test 5
+  it("rejects contributors missing from a private round's allowlist", async() => {
+    await program.methods.allowContributor(admin.publicKey).accounts({
+      poolAccount: poolPDA,
+    })
+    .rpc();
 
-    const data = await program.account.pool.fetch(differentPoolPDA)
-    console.log("data projects", data.projects);
+    try {
+      await program.methods.voteForProject(new BN(10)).accounts({
+        poolAccount: poolPDA,
+        projectAccount: projectPDA1,
+        voterSig: voter1.publicKey,
+      })
+      .signers([voter1])
+      .rpc();
+      assert.fail("non-listed contributor should be rejected");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "ContributorNotAllowed");
+    }
+
+    await program.methods.allowContributor(voter1.publicKey).accounts({
+      poolAccount: poolPDA,
+    })
+    .rpc();
+
+    const voteTx = await program.methods.voteForProject(new BN(10)).accounts({
+      poolAccount: poolPDA,
+      projectAccount: projectPDA1,
+      voterSig: voter1.publicKey,
+    })
+    .signers([voter1])
+    .rpc();
+
+    console.log("Listed contributor voted in the private round", voteTx);
+
+    const data = await program.account.pool.fetch(poolPDA)
+    assert.equal(data.contributorAllowlist.length, 2);
   });
 
-  // Test 4
-  it("votes for the project and distributes the rewards", async() => {
-    const distribIx = await program.methods.distributeEscrowAmount().accounts({
-      escrowAccount: escrowPDA,
+  // Test 6
+  it("sums repeat contributions into a single contributor", async() => {
+    await program.methods.allowContributor(voter2.publicKey).accounts({
+      poolAccount: poolPDA,
+    })
+    .rpc();
+
+    const before = await program.account.project.fetch(projectPDA1)
+
+    const voteIx = await program.methods.voteForProject(new BN(16)).accounts({
       poolAccount: poolPDA,
       projectAccount: projectPDA1,
+      voterSig: voter2.publicKey,
     })
     .instruction();
 
-    const voteTx = await program.methods.voteForProject(new BN(10)).accounts({
+    const voteTx = await program.methods.voteForProject(new BN(9)).accounts({
       poolAccount: poolPDA,
       projectAccount: projectPDA1,
+      voterSig: voter2.publicKey,
     })
-    .postInstructions([distribIx])
+    .preInstructions([voteIx])
+    .signers([voter2])
     .rpc();
-    
-    console.log("Successfully voted on the project and distributed weighted rewards", voteTx);
 
-    const ant = await program.account.project.fetch(projectPDA1)
-    console.log("amount", ant.distributedAmt.toString());
+    console.log("Same contributor voted twice", voteTx);
+
+    const after = await program.account.project.fetch(projectPDA1)
+    const entries = after.contributions.filter((c) => c.contributor.equals(voter2.publicKey));
+    assert.equal(entries.length, 1);
+    assert.equal(entries[0].amount.toNumber(), 25);
+    assert.equal(after.votesCount.toNumber(), before.votesCount.toNumber() + 1);
+
+    // sqrt(25) is taken once, not sqrt(16) + sqrt(9)
+    const sqrtSum = after.contributions
+      .map((c) => Math.floor(Math.sqrt(c.amount.toNumber())))
+      .reduce((a, b) => a + b, 0);
+    assert.equal(after.matchScore.toString(), (sqrtSum * sqrtSum).toString());
+  });
+
+  // Test 26
+  it("distributes the rewards once the round ends and takes no contribution after", async() => {
+    // the pool of test 3 never closes
+    try {
+      await program2.methods.distributeEscrowAmount(false).accounts({
+        escrowAccount: differentEscrowPDA,
+        poolAccount: differentPoolPDA,
+        auditAccount: findAuditPDA(differentPoolPDA, program.programId),
+      })
+      .rpc();
+      assert.fail("an open round should not be distributed");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "RoundNotEnded");
+    }
+
+    await waitForRoundEnd(program, poolPDA);
+
+    const distribTx = await program.methods.distributeEscrowAmount(false).accounts({
+      escrowAccount: escrowPDA,
+      poolAccount: poolPDA,
+      auditAccount: findAuditPDA(poolPDA, program.programId),
+    })
+    .remainingAccounts([{ pubkey: projectPDA1, isWritable: true, isSigner: false }])
+    .rpc();
+
+    console.log("Distributed weighted rewards", distribTx);
+
+    const project = await program.account.project.fetch(projectPDA1)
+    assert.equal(project.distributedAmt.toNumber(), 10000);
+
+    try {
+      await program.methods.voteForProject(new BN(10)).accounts({
+        poolAccount: poolPDA,
+        projectAccount: projectPDA1,
+      })
+      .rpc();
+      assert.fail("a distributed round should take no more contributions");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "AlreadyDistributed");
+    }
+  });
+
+  // Test 7
+  it("refunds the deposit when no project is eligible", async() => {
+    const poolIx = await program3.methods.initializePool(new BN(0), await roundEnd(connection, 0)).accounts({
+      poolAccount: emptyRoundPoolPDA,
+    }).instruction();
+
+    const projectIx = await program3.methods.initializeProject("Empty Round Project").accounts({
+      projectAccount: emptyRoundProjectPDA,
+      poolAccount: emptyRoundPoolPDA,
+    }).instruction();
+
+    const addProjectIx = await program3.methods.addProjectToPool().accounts({
+      escrowAccount: emptyRoundEscrowPDA,
+      poolAccount: emptyRoundPoolPDA,
+      projectAccount: emptyRoundProjectPDA,
+    }).instruction();
+
+    await program3.methods.initializeEscrow(new BN(0), new BN(10000)).accounts({
+      escrowAccount: emptyRoundEscrowPDA,
+    })
+    .postInstructions([poolIx, projectIx, addProjectIx])
+    .rpc();
+
+    await waitForRoundEnd(program3, emptyRoundPoolPDA);
+    const distribTx = await program3.methods.distributeEscrowAmount(true).accounts({
+      escrowAccount: emptyRoundEscrowPDA,
+      poolAccount: emptyRoundPoolPDA,
+      auditAccount: findAuditPDA(emptyRoundPoolPDA, program.programId),
+    })
+    .remainingAccounts([{ pubkey: emptyRoundProjectPDA, isWritable: true, isSigner: false }])
+    .rpc();
+
+    console.log("Distributed a round without eligible projects", distribTx);
+
+    const escrow = await program3.account.escrow.fetch(emptyRoundEscrowPDA)
+    assert.equal(escrow.creatorDepositAmount.toNumber(), 0);
+    assert.equal(escrow.refundedAmount.toNumber(), 10000);
+
+    const project = await program3.account.project.fetch(emptyRoundProjectPDA)
+    assert.equal(project.distributedAmt.toNumber(), 0);
+  });
+
+  // Test 8
+  it("allocates a large matching balance without overflowing", async() => {
+    const [escrow, pool] = findRoundPDAs(admin4.publicKey, program.programId);
+    const projectA = findProjectPDA(pool, admin4.publicKey, program.programId);
+    const projectB = findProjectPDA(pool, projectOwner1.publicKey, program.programId);
+
+    const poolIx = await program4.methods.initializePool(new BN(0), await roundEnd(connection, ROUND_SLOTS)).accounts({
+      poolAccount: pool,
+    }).instruction();
+
+    await program4.methods.initializeEscrow(new BN(0), new BN("1000000000000000000")).accounts({
+      escrowAccount: escrow,
+    })
+    .postInstructions([poolIx])
+    .rpc();
+
+    for (const [owner, project] of [[admin4, projectA], [projectOwner1, projectB]] as const) {
+      const addProjectIx = await program4.methods.addProjectToPool().accounts({
+        escrowAccount: escrow,
+        poolAccount: pool,
+        projectAccount: project,
+        projectOwner: owner.publicKey,
+      }).instruction();
+
+      await program4.methods.initializeProject("Large Round Project").accounts({
+        projectAccount: project,
+        poolAccount: pool,
+        projectOwner: owner.publicKey,
+      })
+      .postInstructions([addProjectIx])
+      .signers([owner])
+      .rpc();
+    }
+
+    const voteBIx = await program4.methods.voteForProject(new BN("1000000000000000000")).accounts({
+      poolAccount: pool,
+      projectAccount: projectB,
+    }).instruction();
+
+    // weights are 4e18 and 1e18, the products with the balance only fit in u128
+    await program4.methods.voteForProject(new BN("4000000000000000000")).accounts({
+      poolAccount: pool,
+      projectAccount: projectA,
+    })
+    .postInstructions([voteBIx])
+    .rpc();
+
+    await waitForRoundEnd(program4, pool);
+    await program4.methods.distributeEscrowAmount(false).accounts({
+      escrowAccount: escrow,
+      poolAccount: pool,
+      auditAccount: findAuditPDA(pool, program.programId),
+    })
+    .remainingAccounts([
+      { pubkey: projectA, isWritable: true, isSigner: false },
+      { pubkey: projectB, isWritable: true, isSigner: false },
+    ])
+    .rpc();
+
+    const a = await program4.account.project.fetch(projectA)
+    const b = await program4.account.project.fetch(projectB)
+    assert.equal(a.distributedAmt.toString(), "800000000000000000");
+    assert.equal(b.distributedAmt.toString(), "200000000000000000");
+  });
+
+  // Test 9
+  it("hands the rounding remainder to the configured recipient", async() => {
+    const { escrow, pool, projects } = await setupRound(
+      program5, [admin5, projectOwner2], new BN(11), 0, await roundEnd(connection, ROUND_SLOTS)
+    );
+    const [projectA, projectB] = projects;
+
+    await program5.methods.setRemainderRecipient({ topProject: {} }).accounts({
+      poolAccount: pool,
+    })
+    .rpc();
+
+    const voteBIx = await program5.methods.voteForProject(new BN(1)).accounts({
+      poolAccount: pool,
+      projectAccount: projectB,
+    }).instruction();
+
+    // weights 4 and 1 split 11 into 8.8 and 2.2, the remainder of 1 goes to the top project
+    await program5.methods.voteForProject(new BN(4)).accounts({
+      poolAccount: pool,
+      projectAccount: projectA,
+    })
+    .postInstructions([voteBIx])
+    .rpc();
+
+    await waitForRoundEnd(program5, pool);
+    await program5.methods.distributeEscrowAmount(false).accounts({
+      escrowAccount: escrow,
+      poolAccount: pool,
+      auditAccount: findAuditPDA(pool, program.programId),
+    })
+    .remainingAccounts(projects.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+    .rpc();
+
+    const a = await program5.account.project.fetch(projectA)
+    const b = await program5.account.project.fetch(projectB)
+    const escrowData = await program5.account.escrow.fetch(escrow)
+    assert.equal(a.distributedAmt.toNumber(), 9);
+    assert.equal(b.distributedAmt.toNumber(), 2);
+    assert.equal(escrowData.refundedAmount.toNumber(), 0);
+    assert.equal(a.distributedAmt.toNumber() + b.distributedAmt.toNumber(), 11);
+  });
+
+  // Test 10
+  it("records an audit of the distribution", async() => {
+    const [escrow, pool] = findRoundPDAs(admin4.publicKey, program.programId);
+    const audit = await program.account.distributionAudit.fetch(findAuditPDA(pool, program.programId))
+    const escrowData = await program.account.escrow.fetch(escrow)
+
+    assert.equal(audit.version, 1);
+    assert.ok(audit.pool.equals(pool));
+    assert.equal(audit.entries.length, 2);
+
+    const totalWeight = audit.entries.reduce((total, entry) => total.add(entry.score), new BN(0));
+    assert.equal(audit.totalWeight.toString(), totalWeight.toString());
+
+    const depositBeforeRemainder = audit.entries.reduce((total, entry) => total.add(entry.payout), new BN(0))
+      .add(escrowData.refundedAmount);
+    for (const entry of audit.entries) {
+      const project = await program.account.project.fetch(entry.project)
+      assert.equal(entry.score.toString(), project.matchScore.toString());
+      assert.equal(entry.payout.toString(), project.distributedAmt.toString());
+      assert.equal(
+        entry.payout.toString(),
+        depositBeforeRemainder.mul(entry.score).div(totalWeight).toString()
+      );
+    }
+  });
+
+  // Test 11
+  it("returns a project's raw total, donors and weight together", async() => {
+    // Tests 4 to 6 contributed 10 (admin), 10 (voter1) and 16 + 9 (voter2)
+    const stats = await program.methods.getProjectStats().accounts({
+      projectAccount: projectPDA1,
+    })
+    .view();
+
+    assert.equal(stats.rawTotal.toNumber(), 45);
+    assert.equal(stats.distinctDonors, 3);
+    assert.equal(stats.weight.toString(), ((3 + 3 + 5) ** 2).toString());
+  });
+
+  // Test 13
+  it("extends a round's end slot but never shortens it", async() => {
+    const [roundPool] = anchor.web3.PublicKey.findProgramAddressSync(
+      [utf8.encode("pool"), admin.publicKey.toBuffer(), roundSeed(1)],
+      program.programId
+    );
+    const endSlot = (await connection.getSlot()) + 1000;
+
+    await program.methods.initializePool(new BN(1), new BN(endSlot)).accounts({
+      poolAccount: roundPool,
+    })
+    .rpc();
+
+    await program.methods.extendRound(new BN(endSlot + 500)).accounts({
+      poolAccount: roundPool,
+    })
+    .rpc();
+
+    const data = await program.account.pool.fetch(roundPool)
+    assert.equal(data.endSlot.toNumber(), endSlot + 500);
+
+    try {
+      await program.methods.extendRound(new BN(endSlot)).accounts({
+        poolAccount: roundPool,
+      })
+      .rpc();
+      assert.fail("shortening a round should be rejected");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "EndSlotNotLater");
+    }
+
+    try {
+      await program2.methods.extendRound(new BN(endSlot + 1000)).accounts({
+        poolAccount: roundPool,
+        poolCreator: admin2.publicKey,
+      })
+      .rpc();
+      assert.fail("only the pool creator may extend the round");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "ConstraintHasOne");
+    }
+  });
+
+  // Test 14
+  it("keeps the accounting of concurrent rounds apart", async() => {
+    const endSlot = await roundEnd(connection, ROUND_SLOTS);
+    const round1 = await setupRound(program6, [admin6, projectOwner1], new BN(1000), 1, endSlot);
+    const round2 = await setupRound(program6, [admin6, projectOwner1], new BN(500), 2, endSlot);
+
+    // round 1 weighs its projects 4:1, round 2 only funds the second project
+    const votes: [typeof round1, number, number][] = [[round1, 0, 16], [round1, 1, 4], [round2, 1, 9]];
+    for (const [round, index, amount] of votes) {
+      await program6.methods.voteForProject(new BN(amount)).accounts({
+        poolAccount: round.pool,
+        projectAccount: round.projects[index],
+      })
+      .rpc();
+    }
+    await waitForRoundEnd(program6, round2.pool);
+
+    const distribute = (escrow, round) => program6.methods.distributeEscrowAmount(false).accounts({
+      escrowAccount: escrow,
+      poolAccount: round.pool,
+      auditAccount: findAuditPDA(round.pool, program.programId),
+    })
+    .remainingAccounts(round.projects.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+    .rpc();
+
+    try {
+      await distribute(round1.escrow, round2);
+      assert.fail("an escrow should only fund its own round");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "RoundMismatch");
+    }
+
+    await distribute(round1.escrow, round1);
+
+    const untouched = await program6.account.escrow.fetch(round2.escrow)
+    assert.equal(untouched.distributed, false);
+    for (const project of round2.projects) {
+      const data = await program6.account.project.fetch(project)
+      assert.equal(data.distributedAmt.toNumber(), 0);
+    }
+
+    await distribute(round2.escrow, round2);
+
+    const payouts = async (round) => Promise.all(round.projects.map(async (project) =>
+      (await program6.account.project.fetch(project)).distributedAmt.toNumber()
+    ));
+    assert.deepEqual(await payouts(round1), [800, 200]);
+    assert.deepEqual(await payouts(round2), [0, 500]);
+
+    const pool1 = await program6.account.pool.fetch(round1.pool)
+    const pool2 = await program6.account.pool.fetch(round2.pool)
+    assert.equal(pool1.totalVotes.toNumber(), 2);
+    assert.equal(pool2.totalVotes.toNumber(), 1);
+  });
+
+  // Test 15
+  it("sums a contributor's contributions across a round's projects", async() => {
+    // admin6 gave 16 and 4 to the two projects of its round 1 in test 14
+    const [, pool] = findRoundPDAs(admin6.publicKey, program.programId, 1);
+    const projects = [admin6, projectOwner1].map((owner) => findProjectPDA(pool, owner.publicKey, program.programId));
+    const remainingAccounts = projects.map((pubkey) => ({ pubkey, isWritable: false, isSigner: false }));
+
+    const total = await program6.methods.getContributorTotal(admin6.publicKey).accounts({
+      poolAccount: pool,
+    })
+    .remainingAccounts(remainingAccounts)
+    .view();
+    assert.equal(total.toNumber(), 20);
+
+    const none = await program6.methods.getContributorTotal(voter1.publicKey).accounts({
+      poolAccount: pool,
+    })
+    .remainingAccounts(remainingAccounts)
+    .view();
+    assert.equal(none.toNumber(), 0);
+  });
+
+  // Test 16
+  it("rejects contributions below the pool's minimum", async() => {
+    const { pool, projects } = await setupRound(program6, [admin6], new BN(100), 3);
+
+    await program6.methods.setMinContribution(new BN(10)).accounts({
+      poolAccount: pool,
+    })
+    .rpc();
+
+    try {
+      await program6.methods.voteForProject(new BN(9)).accounts({
+        poolAccount: pool,
+        projectAccount: projects[0],
+      })
+      .rpc();
+      assert.fail("dust contributions should be rejected");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "ContributionTooSmall");
+    }
+
+    await program6.methods.voteForProject(new BN(10)).accounts({
+      poolAccount: pool,
+      projectAccount: projects[0],
+    })
+    .rpc();
+
+    const project = await program6.account.project.fetch(projects[0])
+    assert.equal(project.voterAmount.toNumber(), 10);
+    assert.equal(project.contributions.length, 1);
+  });
+
+  // Test 17
+  it("distributes identically whatever order contributions and projects come in", async() => {
+    const owners = [admin5, projectOwner2];
+    const endSlot = await roundEnd(connection, ROUND_SLOTS);
+    const roundA = await setupRound(program5, owners, new BN(1003), 1, endSlot);
+    const roundB = await setupRound(program5, owners, new BN(1003), 2, endSlot);
+
+    // the same contributions per project, from the same contributors, in reverse order
+    const contributions: [number, anchor.web3.Keypair, number][] = [
+      [0, voter3, 25], [1, voter4, 7], [0, voter4, 3], [1, voter3, 12],
+    ];
+    for (const [round, ordered] of [[roundA, contributions], [roundB, [...contributions].reverse()]] as const) {
+      for (const [index, voter, amount] of ordered) {
+        await program5.methods.voteForProject(new BN(amount)).accounts({
+          poolAccount: round.pool,
+          projectAccount: round.projects[index],
+          voterSig: voter.publicKey,
+        })
+        .signers([voter])
+        .rpc();
+      }
+    }
+    await waitForRoundEnd(program5, roundB.pool);
+
+    for (const [round, projects] of [[roundA, roundA.projects], [roundB, [...roundB.projects].reverse()]] as const) {
+      await program5.methods.distributeEscrowAmount(false).accounts({
+        escrowAccount: round.escrow,
+        poolAccount: round.pool,
+        auditAccount: findAuditPDA(round.pool, program.programId),
+      })
+      .remainingAccounts(projects.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+      .rpc();
+    }
+
+    // per project owner, both rounds end up with the same score and payout
+    const outcome = async (round) => Promise.all(round.projects.map(async (project) => {
+      const data = await program5.account.project.fetch(project)
+      return [data.matchScore.toString(), data.distributedAmt.toString()];
+    }));
+    assert.deepEqual(await outcome(roundA), await outcome(roundB));
+
+    // and the audit lists the projects by address, not by the order they were passed in
+    for (const round of [roundA, roundB]) {
+      const audit = await program5.account.distributionAudit.fetch(findAuditPDA(round.pool, program.programId))
+      const keys = audit.entries.map((entry) => entry.project.toBase58());
+      const sorted = [...round.projects].sort((a, b) => Buffer.compare(a.toBuffer(), b.toBuffer()));
+      assert.deepEqual(keys, sorted.map((key) => key.toBase58()));
+    }
+  });
+
+  // Test 12
+  it("rejects distributing a round where every project has zero weight", async() => {
+    const { escrow, pool, projects } = await setupRound(
+      program6, [admin6, projectOwner1], new BN(1000), 0, await roundEnd(connection, ROUND_SLOTS)
+    );
+
+    // zero-amount votes make both projects eligible without giving them any weight
+    for (const project of projects) {
+      await program6.methods.voteForProject(new BN(0)).accounts({
+        poolAccount: pool,
+        projectAccount: project,
+      })
+      .rpc();
+    }
+    await waitForRoundEnd(program6, pool);
+
+    try {
+      await program6.methods.distributeEscrowAmount(false).accounts({
+        escrowAccount: escrow,
+        poolAccount: pool,
+        auditAccount: findAuditPDA(pool, program.programId),
+      })
+      .remainingAccounts(projects.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+      .rpc();
+      assert.fail("a round without weight should not be distributed");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "NothingToDistribute");
+    }
+
+    const escrowData = await program6.account.escrow.fetch(escrow)
+    assert.equal(escrowData.distributed, false);
+    assert.equal(escrowData.creatorDepositAmount.toNumber(), 1000);
+    assert.equal(escrowData.refundedAmount.toNumber(), 0);
+    for (const project of projects) {
+      const data = await program6.account.project.fetch(project)
+      assert.equal(data.distributedAmt.toNumber(), 0);
+    }
+  });
+
+  // Test 18
+  it("gives a lone eligible project the whole deposit once it has weight", async() => {
+    const owners = [admin6, projectOwner1];
+    const endSlot = await roundEnd(connection, ROUND_SLOTS);
+    const weightless = await setupRound(program6, owners, new BN(1001), 4, endSlot);
+    const weighted = await setupRound(program6, owners, new BN(1001), 13, endSlot);
+
+    // only the first project of each round receives a contribution, a zero-amount one gives it no weight
+    for (const [round, amount] of [[weightless, 0], [weighted, 3]] as const) {
+      await program6.methods.voteForProject(new BN(amount)).accounts({
+        poolAccount: round.pool,
+        projectAccount: round.projects[0],
+      })
+      .rpc();
+    }
+    await waitForRoundEnd(program6, weighted.pool);
+
+    const distribute = (round) => program6.methods.distributeEscrowAmount(false).accounts({
+      escrowAccount: round.escrow,
+      poolAccount: round.pool,
+      auditAccount: findAuditPDA(round.pool, program.programId),
+    })
+    .remainingAccounts(round.projects.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+    .rpc();
+
+    try {
+      await distribute(weightless);
+      assert.fail("a lone project without weight should not be distributed to");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "NothingToDistribute");
+    }
+    assert.equal((await program6.account.escrow.fetch(weightless.escrow)).distributed, false);
+
+    await distribute(weighted);
+
+    const [winner, other] = await Promise.all(weighted.projects.map((project) => program6.account.project.fetch(project)));
+    assert.equal(winner.distributedAmt.toNumber(), 1001);
+    assert.equal(other.distributedAmt.toNumber(), 0);
+
+    const escrowData = await program6.account.escrow.fetch(weighted.escrow);
+    assert.equal(escrowData.creatorDepositAmount.toNumber(), 1001);
+    assert.equal(escrowData.refundedAmount.toNumber(), 0);
+  });
+
+  // Test 19
+  it("weighs an early contribution more than an identical late one", async() => {
+    const { pool, projects } = await setupRound(program6, [admin6, projectOwner1], new BN(100), 5);
+
+    // a 50% boost for the next few slots, set before anyone contributes
+    const deadline = await roundEnd(connection, 20);
+    await program6.methods.setEarlyBoost(5000, deadline).accounts({
+      poolAccount: pool,
+    })
+    .rpc();
+
+    await program6.methods.voteForProject(new BN(100)).accounts({
+      poolAccount: pool,
+      projectAccount: projects[0],
+    })
+    .rpc();
+
+    // past the deadline the next contribution is a late one
+    await waitForSlot(connection, deadline.toNumber() + 1);
+    await program6.methods.voteForProject(new BN(100)).accounts({
+      poolAccount: pool,
+      projectAccount: projects[1],
+    })
+    .rpc();
+
+    const [early, late] = await Promise.all(projects.map((project) => program6.account.project.fetch(project)));
+    assert.equal(early.voterAmount.toNumber(), 100);
+    assert.equal(late.voterAmount.toNumber(), 100);
+    // sqrt(150) rounds down to 12
+    assert.equal(early.matchScore.toNumber(), 144);
+    assert.equal(late.matchScore.toNumber(), 100);
+
+    // the boost can no longer be moved once contributions are in
+    try {
+      await program6.methods.setEarlyBoost(5000, OPEN_END_SLOT).accounts({
+        poolAccount: pool,
+      })
+      .rpc();
+      assert.fail("the early boost should be fixed once the round started");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "RoundAlreadyStarted");
+    }
+  });
+
+  // Test 20
+  it("reports the matching still available to a round", async() => {
+    const { escrow, pool, projects } = await setupRound(
+      program6, [admin6], new BN(700), 6, await roundEnd(connection, ROUND_SLOTS)
+    );
+    const matchingBalance = () => program6.methods.getMatchingBalance().accounts({
+      escrowAccount: escrow,
+      poolAccount: pool,
+    })
+    .view();
+
+    assert.equal((await matchingBalance()).toNumber(), 700);
+
+    await program6.methods.voteForProject(new BN(4)).accounts({
+      poolAccount: pool,
+      projectAccount: projects[0],
+    })
+    .rpc();
+    assert.equal((await matchingBalance()).toNumber(), 700);
+
+    await waitForRoundEnd(program6, pool);
+    await program6.methods.distributeEscrowAmount(false).accounts({
+      escrowAccount: escrow,
+      poolAccount: pool,
+      auditAccount: findAuditPDA(pool, program.programId),
+    })
+    .remainingAccounts(projects.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+    .rpc();
+    assert.equal((await matchingBalance()).toNumber(), 0);
+  });
+
+  // Test 21
+  it("carries an unallocated deposit over to the linked next round", async() => {
+    const first = await setupRound(program6, [admin6], new BN(1000), 7, await roundEnd(connection, 0));
+    const next = await setupRound(program6, [admin6], new BN(500), 8);
+
+    await program6.methods.setCarryoverTo(next.pool).accounts({
+      poolAccount: first.pool,
+    })
+    .rpc();
+
+    // nobody contributed to the first round, its deposit is set aside
+    await waitForRoundEnd(program6, first.pool);
+    await program6.methods.distributeEscrowAmount(false).accounts({
+      escrowAccount: first.escrow,
+      poolAccount: first.pool,
+      auditAccount: findAuditPDA(first.pool, program.programId),
+    })
+    .remainingAccounts(first.projects.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+    .rpc();
+    assert.equal((await program6.account.pool.fetch(first.pool)).carryoverAmount.toNumber(), 1000);
+
+    await program6.methods.carryOver().accounts({
+      poolAccount: first.pool,
+      successorPool: next.pool,
+      successorEscrow: next.escrow,
+    })
+    .rpc();
+
+    assert.equal((await program6.account.pool.fetch(first.pool)).carryoverAmount.toNumber(), 0);
+    const nextEscrow = await program6.account.escrow.fetch(next.escrow);
+    assert.equal(nextEscrow.creatorDepositAmount.toNumber(), 1500);
+  });
+
+  // Test 22
+  it("records a batch of contributions at once or not at all", async() => {
+    const { pool, projects } = await setupRound(program6, [admin6, projectOwner1], new BN(100), 9);
+    const remainingAccounts = projects.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }));
+
+    await program6.methods.contributeBatch([
+      { project: projects[0], amount: new BN(9) },
+      { project: projects[1], amount: new BN(16) },
+      { project: projects[0], amount: new BN(7) },
+    ]).accounts({
+      poolAccount: pool,
+    })
+    .remainingAccounts(remainingAccounts)
+    .rpc();
+
+    const [first, second] = await Promise.all(projects.map((project) => program6.account.project.fetch(project)));
+    assert.equal(first.voterAmount.toNumber(), 16);
+    assert.equal(first.votesCount.toNumber(), 1);
+    assert.equal(second.voterAmount.toNumber(), 16);
+    assert.equal((await program6.account.pool.fetch(pool)).totalVotes.toNumber(), 2);
+
+    // a project outside the pool fails the batch after a valid contribution
+    const [, otherPool] = findRoundPDAs(admin6.publicKey, program.programId, 8);
+    const outsider = findProjectPDA(otherPool, admin6.publicKey, program.programId);
+    try {
+      await program6.methods.contributeBatch([
+        { project: projects[1], amount: new BN(20) },
+        { project: outsider, amount: new BN(20) },
+      ]).accounts({
+        poolAccount: pool,
+      })
+      .remainingAccounts(remainingAccounts)
+      .rpc();
+      assert.fail("a batch with a project outside the pool should be rejected");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "ProjectNotInPool");
+    }
+
+    const unchanged = await program6.account.project.fetch(projects[1]);
+    assert.equal(unchanged.voterAmount.toNumber(), 16);
+  });
+
+  // Test 23
+  it("grows a project's account as contributors keep coming", async() => {
+    const { pool, projects } = await setupRound(program6, [admin6], new BN(100), 10);
+
+    // more distinct contributors than the initial 1024 bytes hold, each pays
+    // the rent of the space their entry takes
+    const contributors = Array.from({ length: 24 }, () => anchor.web3.Keypair.generate());
+    for (const contributor of contributors) {
+      const fundIx = anchor.web3.SystemProgram.transfer({
+        fromPubkey: admin6.publicKey,
+        toPubkey: contributor.publicKey,
+        lamports: anchor.web3.LAMPORTS_PER_SOL / 100,
+      });
+
+      await program6.methods.voteForProject(new BN(1)).accounts({
+        poolAccount: pool,
+        projectAccount: projects[0],
+        voterSig: contributor.publicKey,
+      })
+      .preInstructions([fundIx])
+      .signers([contributor])
+      .rpc();
+    }
+
+    const project = await program6.account.project.fetch(projects[0]);
+    assert.equal(project.contributions.length, contributors.length);
+    assert.equal(project.votesCount.toNumber(), contributors.length);
+
+    const accountInfo = await connection.getAccountInfo(projects[0]);
+    assert.isAbove(accountInfo.data.length, 1024);
+  });
+
+  // Test 24
+  it("refunds an escrow an earlier attempt left unsettled", async() => {
+    const { escrow, pool, projects } = await setupRound(
+      program6, [admin6], new BN(400), 11, await roundEnd(connection, 0)
+    );
+    const distribute = (refundUnallocated: boolean) => program6.methods.distributeEscrowAmount(refundUnallocated).accounts({
+      escrowAccount: escrow,
+      poolAccount: pool,
+      auditAccount: findAuditPDA(pool, program.programId),
+    })
+    .remainingAccounts(projects.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+    .rpc();
+    await waitForRoundEnd(program6, pool);
+
+    // without an eligible project, a refund or a next round the deposit stays in escrow
+    await distribute(false);
+    assert.equal((await program6.account.escrow.fetch(escrow)).distributed, false);
+
+    await distribute(true);
+
+    const escrowData = await program6.account.escrow.fetch(escrow);
+    assert.equal(escrowData.distributed, true);
+    assert.equal(escrowData.refundedAmount.toNumber(), 400);
+    const audit = await program6.account.distributionAudit.fetch(findAuditPDA(pool, program.programId));
+    assert.ok(audit.pool.equals(pool));
+    assert.equal(audit.entries.length, 1);
+    assert.equal(audit.entries[0].payout.toNumber(), 0);
+  });
+
+  // Test 25
+  it("caps a pool at the projects one distribution can audit", async() => {
+    const MAX_AUDITED_PROJECTS = 16;
+    const owners = Array.from({ length: MAX_AUDITED_PROJECTS + 1 }, () => anchor.web3.Keypair.generate());
+
+    // owners pay for their project accounts
+    const fundTx = new anchor.web3.Transaction().add(...owners.map((owner) => anchor.web3.SystemProgram.transfer({
+      fromPubkey: admin6.publicKey,
+      toPubkey: owner.publicKey,
+      lamports: anchor.web3.LAMPORTS_PER_SOL / 50,
+    })));
+    await program6.provider.sendAndConfirm(fundTx);
+
+    const { escrow, pool } = await setupRound(program6, owners.slice(0, MAX_AUDITED_PROJECTS), new BN(100), 12);
+
+    const extra = owners[MAX_AUDITED_PROJECTS];
+    const project = findProjectPDA(pool, extra.publicKey, program.programId);
+    const addProjectIx = await program6.methods.addProjectToPool().accounts({
+      escrowAccount: escrow,
+      poolAccount: pool,
+      projectAccount: project,
+      projectOwner: extra.publicKey,
+    }).instruction();
+
+    try {
+      await program6.methods.initializeProject("One Too Many").accounts({
+        projectAccount: project,
+        poolAccount: pool,
+        projectOwner: extra.publicKey,
+      })
+      .postInstructions([addProjectIx])
+      .signers([extra])
+      .rpc();
+      assert.fail("a pool should not take more projects than a distribution can audit");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "TooManyProjects");
+    }
+
+    assert.equal((await program6.account.pool.fetch(pool)).totalProjects, MAX_AUDITED_PROJECTS);
+  });
+
+  // Test 27
+  it("grows a pool's account as its allowlist grows", async() => {
+    const [, pool] = findRoundPDAs(admin6.publicKey, program.programId, 14);
+    await program6.methods.initializePool(new BN(14), OPEN_END_SLOT).accounts({
+      poolAccount: pool,
+    })
+    .rpc();
+
+    // more contributors than the initial 1024 bytes hold, the creator pays for the rest
+    const contributors = Array.from({ length: 30 }, () => anchor.web3.Keypair.generate().publicKey);
+    for (const contributor of contributors) {
+      await program6.methods.allowContributor(contributor).accounts({
+        poolAccount: pool,
+      })
+      .rpc();
+    }
+
+    const data = await program6.account.pool.fetch(pool);
+    assert.equal(data.contributorAllowlist.length, contributors.length);
+
+    const accountInfo = await connection.getAccountInfo(pool);
+    assert.isAbove(accountInfo.data.length, 1024);
+  });
+
+  // Test 28
+  it("never hands the remainder to a project without weight", async() => {
+    const { escrow, pool, projects } = await setupRound(
+      program6, [admin6, projectOwner1], new BN(1000), 15, await roundEnd(connection, ROUND_SLOTS)
+    );
+
+    await program6.methods.setMaxMatchPerProject(new BN(500)).accounts({
+      poolAccount: pool,
+    })
+    .rpc();
+    await program6.methods.setRemainderRecipient({ topProject: {} }).accounts({
+      poolAccount: pool,
+    })
+    .rpc();
+
+    // the lone eligible project is capped at 500, the other one never received a contribution
+    await program6.methods.voteForProject(new BN(4)).accounts({
+      poolAccount: pool,
+      projectAccount: projects[0],
+    })
+    .rpc();
+    await waitForRoundEnd(program6, pool);
+
+    await program6.methods.distributeEscrowAmount(false).accounts({
+      escrowAccount: escrow,
+      poolAccount: pool,
+      auditAccount: findAuditPDA(pool, program.programId),
+    })
+    .remainingAccounts(projects.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+    .rpc();
+
+    const [capped, ineligible] = await Promise.all(projects.map((project) => program6.account.project.fetch(project)));
+    assert.equal(capped.distributedAmt.toNumber(), 500);
+    assert.equal(ineligible.distributedAmt.toNumber(), 0);
+
+    const escrowData = await program6.account.escrow.fetch(escrow);
+    assert.equal(escrowData.refundedAmount.toNumber(), 500);
+    assert.equal(escrowData.creatorDepositAmount.toNumber(), 500);
+  });
+
+  // Test 29
+  it("rejects adding a project with the escrow of another round", async() => {
+    const { pool } = await setupRound(program6, [], new BN(1000), 16);
+    const [otherRoundEscrow] = findRoundPDAs(admin6.publicKey, program.programId, 15);
+    const project = findProjectPDA(pool, projectOwner2.publicKey, program.programId);
+
+    const addProjectIx = await program6.methods.addProjectToPool().accounts({
+      escrowAccount: otherRoundEscrow,
+      poolAccount: pool,
+      projectAccount: project,
+      projectOwner: projectOwner2.publicKey,
+    }).instruction();
+
+    try {
+      await program6.methods.initializeProject("Wrong Round").accounts({
+        projectAccount: project,
+        poolAccount: pool,
+        projectOwner: projectOwner2.publicKey,
+      })
+      .postInstructions([addProjectIx])
+      .signers([projectOwner2])
+      .rpc();
+      assert.fail("a project should only be added with its own round's escrow");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "RoundMismatch");
+    }
+
+    assert.equal((await program6.account.pool.fetch(pool)).projects.length, 0);
   });
 });
 
@@ -173,4 +1163,102 @@ async function airdrop(user, provider) {
   });
 
   console.log(`Tx Complete: https://explorer.solana.com/tx/${airdropSignature}?cluster=Localnet`)
-}
\ No newline at end of file
+}
+
+function findRoundPDAs(creator: anchor.web3.PublicKey, programId: anchor.web3.PublicKey, roundId = 0) {
+  const [escrow] = anchor.web3.PublicKey.findProgramAddressSync(
+    [utf8.encode("escrow"), creator.toBuffer(), roundSeed(roundId)],
+    programId
+  );
+  const [pool] = anchor.web3.PublicKey.findProgramAddressSync(
+    [utf8.encode("pool"), creator.toBuffer(), roundSeed(roundId)],
+    programId
+  );
+  return [escrow, pool];
+}
+
+// Little-endian round id, the last seed of a pool
+function roundSeed(roundId: number) {
+  return new BN(roundId).toArrayLike(Buffer, "le", 8);
+}
+
+function findAuditPDA(pool: anchor.web3.PublicKey, programId: anchor.web3.PublicKey) {
+  const [audit] = anchor.web3.PublicKey.findProgramAddressSync(
+    [utf8.encode("audit"), pool.toBytes()],
+    programId
+  );
+  return audit;
+}
+
+function findProjectPDA(pool: anchor.web3.PublicKey, owner: anchor.web3.PublicKey, programId: anchor.web3.PublicKey) {
+  const [project] = anchor.web3.PublicKey.findProgramAddressSync(
+    [utf8.encode("project"), pool.toBytes(), owner.toBuffer()],
+    programId
+  );
+  return project;
+}
+
+// End slot of a round that stays open for the given number of slots from now
+async function roundEnd(connection: anchor.web3.Connection, slots: number) {
+  return new BN((await connection.getSlot()) + slots);
+}
+
+// Waits until the given slot is being produced
+async function waitForSlot(connection: anchor.web3.Connection, slot: number) {
+  while ((await connection.getSlot()) < slot) {
+    await new Promise((resolve) => setTimeout(resolve, 400));
+  }
+}
+
+// Waits until slots past the pool's end slot are being produced, so it can be distributed
+async function waitForRoundEnd(program: Program<Solquad>, pool: anchor.web3.PublicKey) {
+  const { endSlot } = await program.account.pool.fetch(pool);
+  await waitForSlot(program.provider.connection, endSlot.toNumber() + 1);
+}
+
+// Creates the escrow and pool of the program's wallet for a round and one project per owner in it
+async function setupRound(
+  program: Program<Solquad>,
+  owners: anchor.web3.Keypair[],
+  deposit: BN,
+  roundId = 0,
+  endSlot = OPEN_END_SLOT,
+) {
+  const creator = program.provider.publicKey;
+  const [escrow, pool] = findRoundPDAs(creator, program.programId, roundId);
+
+  const poolIx = await program.methods.initializePool(new BN(roundId), endSlot).accounts({
+    poolAccount: pool,
+  }).instruction();
+
+  await program.methods.initializeEscrow(new BN(roundId), deposit).accounts({
+    escrowAccount: escrow,
+  })
+  .postInstructions([poolIx])
+  .rpc();
+
+  const projects = [];
+  for (const owner of owners) {
+    const project = findProjectPDA(pool, owner.publicKey, program.programId);
+
+    const addProjectIx = await program.methods.addProjectToPool().accounts({
+      escrowAccount: escrow,
+      poolAccount: pool,
+      projectAccount: project,
+      projectOwner: owner.publicKey,
+    }).instruction();
+
+    await program.methods.initializeProject("Round Project").accounts({
+      projectAccount: project,
+      poolAccount: pool,
+      projectOwner: owner.publicKey,
+    })
+    .postInstructions([addProjectIx])
+    .signers([owner])
+    .rpc();
+
+    projects.push(project);
+  }
+
+  return { escrow, pool, projects };
+}